@@ -0,0 +1,3222 @@
+// SPDX-FileCopyrightText: 2024 Sebastian Rasor <https://www.sebastianrasor.com/contact>
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Fetching ESPN schedules and computing opponent-adjusted ratings from them. Everything here
+//! besides the fetching is free of I/O, so `compute_ratings` can be called on hand-built schedules.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use clap::ValueEnum;
+use futures::future::BoxFuture;
+use futures::{stream, FutureExt, StreamExt, TryStreamExt};
+use indicatif::{ParallelProgressIterator, ProgressBar, ProgressFinish, ProgressStyle};
+use log::{debug, info, trace, warn};
+use rayon::prelude::*;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Number;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SeasonTypeFilter {
+    Regular,
+    Postseason,
+    All,
+}
+
+impl SeasonTypeFilter {
+    /// Events without a season type are assumed to belong to whichever part of the season was
+    /// requested.
+    pub fn includes(&self, event: &Event) -> bool {
+        let Some(season_type) = &event.season_type else {
+            return true;
+        };
+        match self {
+            SeasonTypeFilter::Regular => season_type.id == "2",
+            SeasonTypeFilter::Postseason => season_type.id == "3",
+            SeasonTypeFilter::All => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum MovTransform {
+    None,
+    Sqrt,
+    Log,
+    Cap,
+}
+
+/// The per-competitor stat that ratings are built from. Anything that compares two competitors on
+/// one scale, where higher is better for the competitor, can slot in here.
+#[derive(Clone, Copy, Debug, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Metric {
+    /// Final score
+    Score,
+}
+
+impl Metric {
+    pub fn value(&self, competitor: &Competitor) -> Option<f64> {
+        match self {
+            Metric::Score => score_value(competitor),
+        }
+    }
+}
+
+/// How a game's result is turned into DEF and OFF.
+#[derive(Clone, Copy, Debug, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Model {
+    /// Compare the result to the opponent's average points in its other games
+    OpponentAverage,
+    /// Compare the margin to the one expected from the previous pass's ratings, iterated to
+    /// convergence like a least-squares power rating
+    RatingDiff,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum TiePolicy {
+    Count,
+    Half,
+    Exclude,
+}
+
+/// Whether forfeits count. A counted forfeit is scored 1-0 against the team that forfeited,
+/// whatever score (if any) ESPN lists for it.
+#[derive(Clone, Copy, Debug, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ForfeitPolicy {
+    Skip,
+    CountAsLoss,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum NameField {
+    Location,
+    DisplayName,
+    Abbreviation,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ref {
+    #[serde(rename = "$ref")]
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaginatedItems {
+    //count: Number,
+    pub page_index: Number,
+    //page_size: Number,
+    pub page_count: Number,
+    pub items: Vec<Ref>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Team {
+    pub id: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub abbreviation: String,
+    #[serde(default)]
+    pub location: String,
+    pub groups: Option<TeamGroups>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamGroups {
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Group {
+    pub name: String,
+    pub short_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rankings {
+    pub rankings: Vec<Ranking>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ranking {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ranking_type: String,
+    pub ranks: Vec<Rank>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rank {
+    pub current: usize,
+    pub team: Team,
+}
+
+impl Team {
+    /// The requested name, or when ESPN leaves that blank the first of the display name, location,
+    /// and id that isn't.
+    pub fn name(&self, name_field: NameField) -> &str {
+        let requested = match name_field {
+            NameField::Location => &self.location,
+            NameField::DisplayName => &self.display_name,
+            NameField::Abbreviation => &self.abbreviation,
+        };
+        [requested, &self.display_name, &self.location]
+            .into_iter()
+            .find(|name| !name.trim().is_empty())
+            .unwrap_or(&self.id)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamSchedule {
+    pub team: Team,
+    pub events: Vec<Event>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamDetails {
+    pub team: Team,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompetitorScore {
+    pub value: Number,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Competitor {
+    pub id: String,
+    pub home_away: Option<String>,
+    pub score: Option<CompetitorScore>,
+    pub winner: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Competition {
+    #[serde(default)]
+    pub neutral_site: bool,
+    pub competitors: Vec<Competitor>,
+    pub status: Option<Status>,
+}
+
+impl Competition {
+    /// ESPN marks games decided in overtime with details like `Final/OT` or `Final/2OT`.
+    pub fn is_overtime(&self) -> bool {
+        self.status
+            .as_ref()
+            .and_then(|status| status.status_type.detail.as_deref())
+            .is_some_and(|detail| detail.contains("OT"))
+    }
+
+    /// ESPN marks forfeits with a `STATUS_FORFEIT` status name, or `STATUS_FORFEIT_HOME_TEAM` and
+    /// `STATUS_FORFEIT_AWAY_TEAM` when it names the side that forfeited.
+    pub fn is_forfeit(&self) -> bool {
+        self.status_name()
+            .is_some_and(|name| name.starts_with("STATUS_FORFEIT"))
+    }
+
+    /// The competitor that forfeited, from the status name or else from whichever side ESPN
+    /// didn't mark as the winner.
+    pub fn forfeited_by(&self) -> Option<&Competitor> {
+        let forfeiting_side = match self.status_name()? {
+            "STATUS_FORFEIT_HOME_TEAM" => "home",
+            "STATUS_FORFEIT_AWAY_TEAM" => "away",
+            "STATUS_FORFEIT" => {
+                let [first, second] = self.competitors.as_slice() else {
+                    return None;
+                };
+                return match (first.winner, second.winner) {
+                    (Some(true), Some(false)) | (Some(true), None) | (None, Some(false)) => {
+                        Some(second)
+                    }
+                    (Some(false), Some(true)) | (None, Some(true)) | (Some(false), None) => {
+                        Some(first)
+                    }
+                    _ => None,
+                };
+            }
+            _ => return None,
+        };
+        self.competitors
+            .iter()
+            .find(|competitor| competitor.home_away.as_deref() == Some(forfeiting_side))
+    }
+
+    fn status_name(&self) -> Option<&str> {
+        self.status.as_ref()?.status_type.name.as_deref()
+    }
+
+    pub fn is_tie(&self) -> bool {
+        let [first, second] = self.competitors.as_slice() else {
+            return false;
+        };
+        matches!(
+            (score_value(first), score_value(second)),
+            (Some(first_score), Some(second_score)) if first_score == second_score
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    #[serde(rename = "type")]
+    pub status_type: StatusType,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusType {
+    pub name: Option<String>,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeasonType {
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Week {
+    pub number: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Event {
+    #[serde(default, deserialize_with = "deserialize_date")]
+    pub date: Option<DateTime<Utc>>,
+    pub season_type: Option<SeasonType>,
+    pub week: Option<Week>,
+    pub competitions: Vec<Competition>,
+}
+
+/// ESPN timestamps usually omit seconds (e.g. `2024-08-31T16:00Z`), which isn't valid RFC 3339.
+fn deserialize_date<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let Some(date) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    if let Ok(date) = DateTime::parse_from_rfc3339(&date) {
+        return Ok(Some(date.with_timezone(&Utc)));
+    }
+    Ok(NaiveDateTime::parse_from_str(&date, "%Y-%m-%dT%H:%MZ")
+        .ok()
+        .map(|date| date.and_utc()))
+}
+
+#[derive(Serialize)]
+pub struct RatingOptions {
+    pub model: Model,
+    pub iterations: u32,
+    pub adjustment_decay: f64,
+    pub mov_cap: Option<f64>,
+    pub mov_transform: MovTransform,
+    pub home_advantage: f64,
+    pub neutralize_opponents: bool,
+    pub recency_halflife: Option<f64>,
+    pub season_type: SeasonTypeFilter,
+    pub week: Option<u32>,
+    pub include_all_opponents: bool,
+    pub name_field: NameField,
+    pub pyth_exponent: f64,
+    pub elo: bool,
+    pub elo_k_factor: f64,
+    pub elo_mov_weight: f64,
+    pub exclude_overtime: bool,
+    pub neutral_only: bool,
+    pub ties: TiePolicy,
+    pub last_n_games: Option<u32>,
+    pub min_opponent_games: u32,
+    pub metric: Metric,
+    pub forfeit_policy: ForfeitPolicy,
+}
+
+impl RatingOptions {
+    pub fn includes_game(&self, event: &Event, competition: &Competition) -> bool {
+        self.exclusion(event, competition).is_none()
+    }
+
+    /// Which filter option leaves the game out, if any.
+    pub fn exclusion(&self, event: &Event, competition: &Competition) -> Option<&'static str> {
+        if !self.season_type.includes(event) {
+            Some("--season-type")
+        } else if !self.through_week(event) {
+            Some("--week")
+        } else if self.exclude_overtime && competition.is_overtime() {
+            Some("--exclude-overtime")
+        } else if self.neutral_only && !competition.neutral_site {
+            Some("--neutral-only")
+        } else if matches!(self.ties, TiePolicy::Exclude) && competition.is_tie() {
+            Some("--ties exclude")
+        } else if matches!(self.forfeit_policy, ForfeitPolicy::Skip) && competition.is_forfeit() {
+            Some("--forfeit-policy skip")
+        } else {
+            None
+        }
+    }
+
+    /// The metric value for one side of a game, or its side of 1-0 for a counted forfeit.
+    pub fn metric_value(&self, competition: &Competition, competitor: &Competitor) -> Option<f64> {
+        self.forfeit_value(competition, competitor)
+            .or_else(|| self.metric.value(competitor))
+    }
+
+    /// The final score for one side of a game, or its side of 1-0 for a counted forfeit.
+    pub fn points(&self, competition: &Competition, competitor: &Competitor) -> Option<f64> {
+        self.forfeit_value(competition, competitor)
+            .or_else(|| score_value(competitor))
+    }
+
+    fn forfeit_value(&self, competition: &Competition, competitor: &Competitor) -> Option<f64> {
+        match self.forfeit_policy {
+            ForfeitPolicy::Skip => None,
+            ForfeitPolicy::CountAsLoss => {
+                competition
+                    .forfeited_by()
+                    .map(|forfeiting| match forfeiting.id == competitor.id {
+                        true => 0.0,
+                        false => 1.0,
+                    })
+            }
+        }
+    }
+
+    /// A forfeit's score wasn't earned on anyone's field, so home advantage doesn't apply to it.
+    pub fn home_field_applies(&self, competition: &Competition) -> bool {
+        !competition.neutral_site && competition.forfeited_by().is_none()
+    }
+
+    pub fn tie_weight(&self, competition: &Competition) -> f64 {
+        match self.ties {
+            TiePolicy::Half if competition.is_tie() => 0.5,
+            _ => 1.0,
+        }
+    }
+
+    /// Week numbers restart in the postseason, so a week cutoff also excludes postseason games.
+    /// Events without a week are kept, as with a missing season type.
+    pub fn through_week(&self, event: &Event) -> bool {
+        let Some(week) = self.week else {
+            return true;
+        };
+        SeasonTypeFilter::Regular.includes(event)
+            && event
+                .week
+                .as_ref()
+                .is_none_or(|event_week| event_week.number <= week)
+    }
+}
+
+#[derive(Default)]
+pub struct Record {
+    pub wins: u32,
+    pub losses: u32,
+    pub ties: u32,
+}
+
+impl std::fmt::Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.ties {
+            0 => write!(f, "{}-{}", self.wins, self.losses),
+            _ => write!(f, "{}-{}-{}", self.wins, self.losses, self.ties),
+        }
+    }
+}
+
+pub struct TeamRating {
+    pub id: String,
+    pub name: String,
+    pub conference_id: Option<String>,
+    pub defense_rating: f64,
+    pub offense_rating: f64,
+    /// DEF and OFF before they're divided by `total_weight`, which is the number of counted games
+    /// unless some games are weighted down.
+    pub defense_total: f64,
+    pub offense_total: f64,
+    pub total_weight: f64,
+    pub elo: Option<f64>,
+    pub games: usize,
+    pub record: Record,
+    pub points_for: f64,
+    pub points_against: f64,
+    pub consistency: f64,
+    pub pythagorean: f64,
+    pub opponent_ids: Vec<String>,
+    pub strength_of_schedule: f64,
+    pub breakdown: Vec<GameContribution>,
+}
+
+/// One counted game's share of a team's ratings; a team's contributions sum to its DEF and OFF.
+pub struct GameContribution {
+    pub date: Option<DateTime<Utc>>,
+    pub opponent_id: String,
+    pub opponent: String,
+    pub points_scored: f64,
+    pub points_allowed: f64,
+    pub weight: f64,
+    pub defense: f64,
+    pub offense: f64,
+    /// The rated margin, after home-field and margin adjustments.
+    pub margin: f64,
+}
+
+impl TeamRating {
+    pub fn overall_rating(&self) -> f64 {
+        self.weighted_rating(1.0, 1.0)
+    }
+
+    pub fn weighted_rating(&self, defense_weight: f64, offense_weight: f64) -> f64 {
+        self.elo
+            .unwrap_or(defense_weight * self.defense_rating + offense_weight * self.offense_rating)
+    }
+}
+
+#[derive(Clone)]
+pub struct Cache {
+    pub dir: PathBuf,
+    pub ttl: Duration,
+}
+
+impl Cache {
+    pub fn new(namespace: &str, ttl: Duration) -> Result<Option<Cache>> {
+        let Some(cache_dir) = dirs::cache_dir() else {
+            return Ok(None);
+        };
+        let dir = cache_dir.join("rasor_ratings").join(namespace);
+        fs::create_dir_all(&dir)?;
+        Ok(Some(Cache { dir, ttl }))
+    }
+
+    /// Entries are named by a hash of the URL that, unlike `DefaultHasher`'s, doesn't change
+    /// between Rust releases, so a toolchain upgrade keeps finding the same files.
+    pub fn path(&self, url: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}.json", fnv1a(url.as_bytes())))
+    }
+
+    pub fn get(&self, url: &str) -> Option<String> {
+        let path = self.path(url);
+        let age = fs::metadata(&path).ok()?.modified().ok()?.elapsed().ok()?;
+        if age > self.ttl {
+            return None;
+        }
+        fs::read_to_string(path).ok()
+    }
+
+    pub fn put(&self, url: &str, body: &str) -> Result<()> {
+        fs::write(self.path(url), body)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, url: &str) -> Result<()> {
+        fs::remove_file(self.path(url))?;
+        Ok(())
+    }
+}
+
+/// 64-bit FNV-1a.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+pub const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// The longest a 429's `Retry-After` is waited out for, so that a server asking for hours doesn't
+/// leave the run hanging without a word.
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Clone)]
+pub struct Fetcher {
+    pub client: Client,
+    pub cache: Option<Cache>,
+    pub max_retries: u32,
+}
+
+impl Fetcher {
+    pub async fn fetch_body(&self, url: &str) -> Result<String> {
+        let mut attempt: u32 = 0;
+        loop {
+            trace!("GET {}", url);
+            let mut retry_after = None;
+            let result = async {
+                let response = self.client.get(url).send().await?;
+                if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                    retry_after = response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(parse_retry_after);
+                }
+                response.error_for_status()?.text().await
+            }
+            .await;
+            match result {
+                Ok(body) => return Ok(body),
+                Err(e) if attempt < self.max_retries && is_transient(&e) => {
+                    // A rate-limited response says when to come back, which beats guessing.
+                    let delay = match retry_after {
+                        Some(retry_after) if retry_after > MAX_RETRY_AFTER => {
+                            warn!(
+                                "{} asked to retry in {} s, waiting {} s instead",
+                                url,
+                                retry_after.as_secs(),
+                                MAX_RETRY_AFTER.as_secs()
+                            );
+                            MAX_RETRY_AFTER
+                        }
+                        Some(retry_after) => retry_after,
+                        None => {
+                            let backoff = RETRY_BASE_DELAY * 2u32.pow(attempt);
+                            let jitter = rand::random_range(0..=backoff.as_millis() as u64);
+                            backoff + Duration::from_millis(jitter)
+                        }
+                    };
+                    debug!(
+                        "retrying {} in {} ms after attempt {} failed: {}",
+                        url,
+                        delay.as_millis(),
+                        attempt + 1,
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub async fn fetch_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        if let Some(cache) = &self.cache {
+            // A truncated or corrupt entry is thrown away and fetched again.
+            if let Some(body) = cache.get(url) {
+                match serde_json::from_str(&body) {
+                    Ok(data) => {
+                        trace!("using the cached response for {}", url);
+                        return Ok(data);
+                    }
+                    Err(_) => cache.remove(url)?,
+                }
+            }
+        }
+        let body = self.fetch_body(url).await?;
+        let data = serde_json::from_str(&body)?;
+        if let Some(cache) = &self.cache {
+            cache.put(url, &body)?;
+        }
+        Ok(data)
+    }
+}
+
+pub const SITE_API_BASE: &str = "https://site.api.espn.com/apis/site/v2";
+
+pub const CORE_API_BASE: &str = "https://sports.core.api.espn.com/v2";
+
+/// Builds ESPN API URLs for the requested league, rooted at the configured API hosts.
+pub struct Endpoints {
+    pub site_api_base: String,
+    pub core_api_base: String,
+    pub sport: String,
+    pub league: String,
+    /// The season type that group team lists and group details are read from (2 is the regular
+    /// season).
+    pub season_type_code: u16,
+}
+
+impl Endpoints {
+    pub fn new(
+        site_api_base: &str,
+        core_api_base: &str,
+        sport: &str,
+        league: &str,
+        season_type_code: u16,
+    ) -> Endpoints {
+        Endpoints {
+            site_api_base: site_api_base.trim_end_matches('/').to_string(),
+            core_api_base: core_api_base.trim_end_matches('/').to_string(),
+            sport: sport.to_string(),
+            league: league.to_string(),
+            season_type_code,
+        }
+    }
+
+    pub fn teams(&self, season: &u16, group: Option<&u16>) -> String {
+        match group {
+            Some(group) => format!(
+                "{}/sports/{}/leagues/{}/seasons/{}/types/{}/groups/{}/teams",
+                self.core_api_base, self.sport, self.league, season, self.season_type_code, group
+            ),
+            None => format!(
+                "{}/sports/{}/leagues/{}/seasons/{}/teams",
+                self.core_api_base, self.sport, self.league, season
+            ),
+        }
+    }
+
+    pub fn group(&self, season: &u16, group_id: &str) -> String {
+        format!(
+            "{}/sports/{}/leagues/{}/seasons/{}/types/{}/groups/{}",
+            self.core_api_base, self.sport, self.league, season, self.season_type_code, group_id
+        )
+    }
+
+    pub fn team(&self, team_id: &str) -> String {
+        format!(
+            "{}/sports/{}/{}/teams/{}",
+            self.site_api_base, self.sport, self.league, team_id
+        )
+    }
+
+    pub fn schedule(&self, team_id: impl std::fmt::Display, season: &u16) -> String {
+        format!(
+            "{}/sports/{}/{}/teams/{}/schedule?season={}",
+            self.site_api_base, self.sport, self.league, team_id, season
+        )
+    }
+
+    pub fn rankings(&self, season: &u16) -> String {
+        format!(
+            "{}/sports/{}/{}/rankings?season={}",
+            self.site_api_base, self.sport, self.league, season
+        )
+    }
+}
+
+/// Where team lists and schedules come from. ESPN is the only provider shipped, but anything that
+/// can produce `TeamSchedule`s, such as another API or a local dataset, can stand in for it.
+pub trait ScheduleProvider: Send + Sync {
+    /// The teams to rate for the season, or just those in `group`.
+    fn team_ids(&self, season: u16, group: Option<u16>) -> BoxFuture<'_, Result<Vec<String>>>;
+
+    fn schedule<'a>(&'a self, team_id: &'a str, season: u16)
+        -> BoxFuture<'a, Result<TeamSchedule>>;
+
+    /// Where a team's schedule is read from, for dry runs and error messages.
+    fn schedule_source(&self, team_id: &str, season: u16) -> String;
+}
+
+pub struct EspnProvider {
+    pub fetcher: Fetcher,
+    pub endpoints: Endpoints,
+    /// Team lists change far less often than scores, so they're cached separately.
+    pub teams_cache: Option<Cache>,
+    pub max_concurrency: usize,
+    pub quiet: bool,
+}
+
+impl ScheduleProvider for EspnProvider {
+    fn team_ids(&self, season: u16, group: Option<u16>) -> BoxFuture<'_, Result<Vec<String>>> {
+        async move {
+            let teams_url = self.endpoints.teams(&season, group.as_ref());
+            if let Some(cached) = self
+                .teams_cache
+                .as_ref()
+                .and_then(|cache| cache.get(&teams_url))
+            {
+                return Ok(serde_json::from_str(&cached)?);
+            }
+            let team_ids = get_team_ids(
+                &self.fetcher,
+                &self.endpoints,
+                self.max_concurrency,
+                &season,
+                group.as_ref(),
+                self.quiet,
+            )
+            .await?;
+            if let Some(cache) = &self.teams_cache {
+                cache.put(&teams_url, &serde_json::to_string(&team_ids)?)?;
+            }
+            Ok(team_ids)
+        }
+        .boxed()
+    }
+
+    fn schedule<'a>(
+        &'a self,
+        team_id: &'a str,
+        season: u16,
+    ) -> BoxFuture<'a, Result<TeamSchedule>> {
+        async move {
+            self.fetcher
+                .fetch_json(&self.endpoints.schedule(team_id, &season))
+                .await
+        }
+        .boxed()
+    }
+
+    fn schedule_source(&self, team_id: &str, season: u16) -> String {
+        self.endpoints.schedule(team_id, &season)
+    }
+}
+
+/// A `Retry-After` header's delay, given either in seconds or as an HTTP date. A date in the past
+/// means no wait.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Server errors, rate limits, and failures to reach the server at all are worth retrying. Anything
+/// else, like a malformed URL or a body cut short, would fail the same way again.
+fn is_transient(error: &reqwest::Error) -> bool {
+    match error.status() {
+        Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+        None => error.is_connect() || error.is_timeout(),
+    }
+}
+
+pub const CONVERGENCE_EPSILON: f64 = 0.001;
+
+/// Read-only lookups shared by every team's rating. It's built once, before any parallel work,
+/// so the rating passes only ever borrow it.
+pub struct ScheduleIndex<'a> {
+    pub schedules_by_id: HashMap<&'a str, &'a TeamSchedule>,
+    pub league_team_ids: HashSet<&'a str>,
+    pub include_all_opponents: bool,
+}
+
+impl<'a> ScheduleIndex<'a> {
+    pub fn new(
+        team_schedules: &'a [TeamSchedule],
+        fbs_team_ids: &[&'a str],
+        options: &RatingOptions,
+    ) -> ScheduleIndex<'a> {
+        ScheduleIndex {
+            schedules_by_id: team_schedules
+                .iter()
+                .map(|team_schedule| (team_schedule.team.id.as_str(), team_schedule))
+                .collect(),
+            league_team_ids: fbs_team_ids.iter().copied().collect(),
+            include_all_opponents: options.include_all_opponents,
+        }
+    }
+
+    /// Whether games against this team count, i.e. it's in the league or all opponents count.
+    pub fn counts(&self, team_id: &str) -> bool {
+        self.include_all_opponents || self.league_team_ids.contains(team_id)
+    }
+}
+
+/// Rates every team with a non-empty schedule. `fbs_team_ids` are the teams of the league being
+/// rated; games against anyone else only count with `include_all_opponents`.
+pub fn compute_ratings(
+    team_schedules: &[TeamSchedule],
+    fbs_team_ids: &[&str],
+    options: &RatingOptions,
+) -> Vec<TeamRating> {
+    compute_ratings_with_progress(
+        team_schedules,
+        fbs_team_ids,
+        options,
+        &ProgressBar::hidden(),
+    )
+}
+
+/// `compute_ratings`, ticking `pb` once per team on every pass.
+pub fn compute_ratings_with_progress(
+    team_schedules: &[TeamSchedule],
+    fbs_team_ids: &[&str],
+    options: &RatingOptions,
+    pb: &ProgressBar,
+) -> Vec<TeamRating> {
+    let mut team_ratings: Vec<TeamRating> = vec![];
+
+    let index = ScheduleIndex::new(team_schedules, fbs_team_ids, options);
+
+    log_skipped_games(team_schedules, &index, options);
+
+    for pass in 1..=options.iterations {
+        pb.reset();
+        pb.set_message(match options.iterations {
+            1 => "Calculating ratings".to_string(),
+            _ => format!("Calculating ratings (pass {})", pass),
+        });
+
+        let adjustments = schedule_adjustments(&team_ratings, options.adjustment_decay);
+        let game_sums: HashMap<&str, TeamGameSums> = team_schedules
+            .par_iter()
+            .map(|team_schedule| {
+                (
+                    team_schedule.team.id.as_str(),
+                    team_game_sums(team_schedule, &index, &adjustments, options),
+                )
+            })
+            .collect();
+        let league_average = league_average(&game_sums);
+
+        let next_team_ratings: Vec<TeamRating> = team_schedules
+            .par_iter()
+            .progress_with(pb.clone())
+            .filter(|team_schedule| !team_schedule.events.is_empty())
+            .filter_map(|team_schedule| {
+                rate_team(
+                    team_schedule,
+                    &index,
+                    &game_sums,
+                    &adjustments,
+                    league_average,
+                    options,
+                )
+            })
+            .collect();
+
+        let previous: HashMap<&str, &TeamRating> = team_ratings
+            .iter()
+            .map(|rating| (rating.id.as_str(), rating))
+            .collect();
+        let max_change = next_team_ratings
+            .iter()
+            .map(|rating| match previous.get(rating.id.as_str()) {
+                Some(prev) => (rating.defense_rating - prev.defense_rating)
+                    .abs()
+                    .max((rating.offense_rating - prev.offense_rating).abs()),
+                None => f64::INFINITY,
+            })
+            .fold(0.0, f64::max);
+
+        match max_change.is_finite() {
+            true => info!(
+                "pass {}: rated {} teams, largest change {:.4}",
+                pass,
+                next_team_ratings.len(),
+                max_change
+            ),
+            false => info!("pass {}: rated {} teams", pass, next_team_ratings.len()),
+        }
+
+        team_ratings = next_team_ratings;
+
+        if max_change < CONVERGENCE_EPSILON {
+            info!("ratings converged after {} passes", pass);
+            break;
+        }
+    }
+
+    if options.elo {
+        let elo_ratings = compute_elo(team_schedules, &index, options);
+        for rating in team_ratings.iter_mut() {
+            rating.elo = elo_ratings.get(&rating.id).copied();
+        }
+    }
+
+    assign_strength_of_schedule(&mut team_ratings);
+
+    team_ratings
+}
+
+/// Games against opponents whose schedules weren't fetched, or that don't list the team among
+/// their competitors, are skipped by `rate_team`, which thins out the affected teams' samples.
+fn log_skipped_games(
+    team_schedules: &[TeamSchedule],
+    index: &ScheduleIndex,
+    options: &RatingOptions,
+) {
+    for team_schedule in team_schedules {
+        if team_schedule.events.is_empty() {
+            debug!(
+                "skipping {}, whose schedule has no events",
+                team_schedule.team.name(NameField::Location)
+            );
+        }
+        for event in &team_schedule.events {
+            let Some(competition) = event.competitions.last() else {
+                continue;
+            };
+            if !options.includes_game(event, competition) {
+                continue;
+            }
+            if competition
+                .competitors
+                .iter()
+                .all(|competitor| competitor.id != team_schedule.team.id)
+            {
+                debug!(
+                    "skipping a game in {}'s schedule that doesn't list them among its competitors ({})",
+                    team_schedule.team.name(NameField::Location),
+                    competition
+                        .competitors
+                        .iter()
+                        .map(|competitor| competitor.id.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(" vs. ")
+                );
+                continue;
+            }
+            let Some(opponent) = competition
+                .competitors
+                .iter()
+                .find(|competitor| competitor.id != team_schedule.team.id)
+            else {
+                continue;
+            };
+            if index.counts(&opponent.id)
+                && !index.schedules_by_id.contains_key(opponent.id.as_str())
+            {
+                debug!(
+                    "skipping {}'s game against team {}, whose schedule was not fetched",
+                    team_schedule.team.name(NameField::Location),
+                    opponent.id
+                );
+            }
+        }
+    }
+}
+
+/// Sets each team's strength of schedule to the average overall rating of its counted opponents.
+fn assign_strength_of_schedule(team_ratings: &mut [TeamRating]) {
+    let overall_ratings: HashMap<String, f64> = team_ratings
+        .iter()
+        .map(|rating| (rating.id.clone(), rating.overall_rating()))
+        .collect();
+    for rating in team_ratings.iter_mut() {
+        let opponent_ratings: Vec<f64> = rating
+            .opponent_ids
+            .iter()
+            .filter_map(|id| overall_ratings.get(id).copied())
+            .collect();
+        if !opponent_ratings.is_empty() {
+            rating.strength_of_schedule =
+                opponent_ratings.iter().sum::<f64>() / opponent_ratings.len() as f64;
+        }
+    }
+}
+
+pub struct PredictionError {
+    pub games: usize,
+    pub mean_absolute_error: f64,
+}
+
+/// How far each counted game's margin was from the difference in the two teams' overall ratings,
+/// weighted the same way as the printed OVR. A game counted by both teams is only measured once,
+/// and games against unrated opponents are left out. Returns `None` when no game qualifies.
+pub fn prediction_error(
+    team_ratings: &[TeamRating],
+    defense_weight: f64,
+    offense_weight: f64,
+) -> Option<PredictionError> {
+    let overall_ratings: HashMap<&str, f64> = team_ratings
+        .iter()
+        .map(|rating| {
+            (
+                rating.id.as_str(),
+                rating.weighted_rating(defense_weight, offense_weight),
+            )
+        })
+        .collect();
+    let mut seen: HashSet<(Option<DateTime<Utc>>, &str, &str)> = HashSet::new();
+    let mut total_error: f64 = 0.0;
+    let mut games: usize = 0;
+    for rating in team_ratings {
+        for game in &rating.breakdown {
+            let Some(opponent_rating) = overall_ratings.get(game.opponent_id.as_str()) else {
+                continue;
+            };
+            let pair = match rating.id.as_str() < game.opponent_id.as_str() {
+                true => (rating.id.as_str(), game.opponent_id.as_str()),
+                false => (game.opponent_id.as_str(), rating.id.as_str()),
+            };
+            if !seen.insert((game.date, pair.0, pair.1)) {
+                continue;
+            }
+            let predicted = overall_ratings[rating.id.as_str()] - opponent_rating;
+            total_error += (game.margin - predicted).abs();
+            games += 1;
+        }
+    }
+    (games > 0).then(|| PredictionError {
+        games,
+        mean_absolute_error: total_error / games as f64,
+    })
+}
+
+pub const ELO_INITIAL_RATING: f64 = 1500.0;
+
+/// Replays every game between rated teams in chronological order, updating both teams' Elo
+/// ratings after each one.
+fn compute_elo(
+    team_schedules: &[TeamSchedule],
+    index: &ScheduleIndex,
+    options: &RatingOptions,
+) -> HashMap<String, f64> {
+    let mut seen: HashSet<(Option<DateTime<Utc>>, &str, &str)> = HashSet::new();
+    let mut games: Vec<(Option<DateTime<Utc>>, f64, &Competition)> = vec![];
+    for team_schedule in team_schedules {
+        for event in &team_schedule.events {
+            let Some(competition) = event.competitions.last() else {
+                continue;
+            };
+            if !options.includes_game(event, competition) {
+                continue;
+            }
+            let [first, second] = competition.competitors.as_slice() else {
+                continue;
+            };
+            if !(index.counts(&first.id) && index.counts(&second.id)) {
+                continue;
+            }
+            // Each game shows up in both teams' schedules.
+            let key = match first.id < second.id {
+                true => (event.date, first.id.as_str(), second.id.as_str()),
+                false => (event.date, second.id.as_str(), first.id.as_str()),
+            };
+            if seen.insert(key) {
+                games.push((event.date, options.tie_weight(competition), competition));
+            }
+        }
+    }
+    games.sort_by_key(|(date, _, _)| *date);
+
+    let mut elo_ratings: HashMap<String, f64> = HashMap::new();
+    for (_, weight, competition) in games {
+        let [first, second] = competition.competitors.as_slice() else {
+            continue;
+        };
+        let (Some(first_score), Some(second_score)) = (
+            options.metric_value(competition, first),
+            options.metric_value(competition, second),
+        ) else {
+            continue;
+        };
+        let first_elo = *elo_ratings.get(&first.id).unwrap_or(&ELO_INITIAL_RATING);
+        let second_elo = *elo_ratings.get(&second.id).unwrap_or(&ELO_INITIAL_RATING);
+        let expected = 1.0 / (1.0 + 10_f64.powf((second_elo - first_elo) / 400.0));
+        let actual = match first_score.total_cmp(&second_score) {
+            Ordering::Greater => 1.0,
+            Ordering::Less => 0.0,
+            Ordering::Equal => 0.5,
+        };
+        let k_factor = weight
+            * options.elo_k_factor
+            * (1.0 + options.elo_mov_weight * (first_score - second_score).abs().ln_1p());
+        let change = k_factor * (actual - expected);
+        elo_ratings.insert(first.id.clone(), first_elo + change);
+        elo_ratings.insert(second.id.clone(), second_elo - change);
+    }
+
+    elo_ratings
+}
+
+pub fn score_value(competitor: &Competitor) -> Option<f64> {
+    competitor.score.as_ref()?.value.as_f64()
+}
+
+/// Centered (defense, offense) ratings from the previous pass, keyed by team id. These are used
+/// to correct each opponent's raw averages for the strength of the teams that opponent played,
+/// scaled by `decay`.
+fn schedule_adjustments(team_ratings: &[TeamRating], decay: f64) -> HashMap<String, (f64, f64)> {
+    if team_ratings.is_empty() {
+        return HashMap::new();
+    }
+    let n = team_ratings.len() as f64;
+    let mean_defense = team_ratings.iter().map(|r| r.defense_rating).sum::<f64>() / n;
+    let mean_offense = team_ratings.iter().map(|r| r.offense_rating).sum::<f64>() / n;
+    team_ratings
+        .iter()
+        .map(|rating| {
+            (
+                rating.id.clone(),
+                (
+                    decay * (rating.defense_rating - mean_defense),
+                    decay * (rating.offense_rating - mean_offense),
+                ),
+            )
+        })
+        .collect()
+}
+
+/// Points per team per game across every counted game, which `--model rating-diff` measures DEF
+/// and OFF from.
+fn league_average(game_sums: &HashMap<&str, TeamGameSums>) -> f64 {
+    let (scored, count) = game_sums.values().fold((0.0, 0), |(scored, count), sums| {
+        (scored + sums.total.scored, count + sums.total.count)
+    });
+    match count {
+        0 => 0.0,
+        _ => scored / count as f64,
+    }
+}
+
+/// Sums over a team's counted games of what it scored and allowed, along with the previous pass's
+/// ratings of the teams it played.
+#[derive(Clone, Copy, Default)]
+pub struct GameSums {
+    pub scored: f64,
+    pub allowed: f64,
+    pub schedule_defense: f64,
+    pub schedule_offense: f64,
+    pub count: usize,
+}
+
+impl GameSums {
+    pub fn add(&mut self, other: &GameSums) {
+        self.scored += other.scored;
+        self.allowed += other.allowed;
+        self.schedule_defense += other.schedule_defense;
+        self.schedule_offense += other.schedule_offense;
+        self.count += other.count;
+    }
+
+    pub fn without(&self, other: &GameSums) -> GameSums {
+        GameSums {
+            scored: self.scored - other.scored,
+            allowed: self.allowed - other.allowed,
+            schedule_defense: self.schedule_defense - other.schedule_defense,
+            schedule_offense: self.schedule_offense - other.schedule_offense,
+            count: self.count - other.count,
+        }
+    }
+}
+
+/// A team's game sums in total and per opponent, so the games against whichever team is being
+/// rated can be taken back out without walking the schedule again.
+pub struct TeamGameSums {
+    pub total: GameSums,
+    pub by_opponent: HashMap<String, GameSums>,
+}
+
+/// Computed once per pass for every team, since each team is the opponent of many others.
+pub fn team_game_sums(
+    team_schedule: &TeamSchedule,
+    index: &ScheduleIndex,
+    adjustments: &HashMap<String, (f64, f64)>,
+    options: &RatingOptions,
+) -> TeamGameSums {
+    let mut sums = TeamGameSums {
+        total: GameSums::default(),
+        by_opponent: HashMap::new(),
+    };
+    for event in rated_events(team_schedule, index, options) {
+        let Some(competition) = event.competitions.last() else {
+            continue;
+        };
+        if !options.includes_game(event, competition) {
+            continue;
+        }
+        if competition.competitors.len() != 2 {
+            continue;
+        }
+        let Some(c_index) = competition
+            .competitors
+            .iter()
+            .position(|competitor| competitor.id == team_schedule.team.id)
+        else {
+            continue;
+        };
+        let competitor = &competition.competitors[c_index];
+        let opponent = &competition.competitors[c_index ^ 1];
+        if !index.counts(&opponent.id) {
+            continue;
+        }
+        let Some(competitor_score_f64) = options.metric_value(competition, competitor) else {
+            continue;
+        };
+        let Some(opponent_score_f64) = options.metric_value(competition, opponent) else {
+            continue;
+        };
+        let (competitor_score_f64, opponent_score_f64) =
+            match options.neutralize_opponents && options.home_field_applies(competition) {
+                true => adjust_home_field(
+                    competitor_score_f64,
+                    opponent_score_f64,
+                    competitor.home_away.as_deref(),
+                    options.home_advantage,
+                ),
+                false => (competitor_score_f64, opponent_score_f64),
+            };
+        let (competitor_score_f64, opponent_score_f64) = transform_margin(
+            competitor_score_f64,
+            opponent_score_f64,
+            options.mov_transform,
+            options.mov_cap,
+        );
+        let (schedule_defense, schedule_offense) =
+            adjustments.get(&opponent.id).copied().unwrap_or_default();
+        let game = GameSums {
+            scored: competitor_score_f64,
+            allowed: opponent_score_f64,
+            schedule_defense,
+            schedule_offense,
+            count: 1,
+        };
+        sums.total.add(&game);
+        sums.by_opponent
+            .entry(opponent.id.clone())
+            .or_default()
+            .add(&game);
+    }
+    sums
+}
+
+/// Why `rate_team` leaves a game out, as listed by `--audit`.
+pub enum SkipReason {
+    NoCompetition,
+    Excluded(&'static str),
+    NotTwoCompetitors,
+    TeamNotListed,
+    OpponentNotCounted,
+    MissingScore,
+    MissingOpponentSchedule,
+    FewOpponentGames(usize),
+    NotRecent(u32),
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::NoCompetition => write!(f, "no competition listed"),
+            SkipReason::Excluded(option) => write!(f, "excluded by {}", option),
+            SkipReason::NotTwoCompetitors => write!(f, "doesn't have exactly two competitors"),
+            SkipReason::TeamNotListed => write!(f, "team isn't among the competitors"),
+            SkipReason::OpponentNotCounted => {
+                write!(
+                    f,
+                    "opponent isn't in the league (see --include-all-opponents)"
+                )
+            }
+            SkipReason::MissingScore => write!(f, "missing score"),
+            SkipReason::MissingOpponentSchedule => write!(f, "opponent's schedule wasn't fetched"),
+            SkipReason::FewOpponentGames(count) => write!(
+                f,
+                "opponent has only {} other counted games (see --min-opponent-games)",
+                count
+            ),
+            SkipReason::NotRecent(last_n_games) => write!(
+                f,
+                "not among the {} most recent games (--last-n-games)",
+                last_n_games
+            ),
+        }
+    }
+}
+
+/// A game that counts toward a team's rating. `scored` and `allowed` are in terms of the metric
+/// after home-field and margin adjustments, while the points are the final score.
+pub struct RatedGame<'a> {
+    pub competition: &'a Competition,
+    pub opponent: &'a Competitor,
+    pub opponent_schedule: &'a TeamSchedule,
+    pub scored: f64,
+    pub allowed: f64,
+    pub points_scored: f64,
+    pub points_allowed: f64,
+    pub opponent_sums: GameSums,
+}
+
+pub fn rated_game<'a>(
+    team_schedule: &TeamSchedule,
+    event: &'a Event,
+    index: &ScheduleIndex<'a>,
+    game_sums: &HashMap<&str, TeamGameSums>,
+    options: &RatingOptions,
+) -> std::result::Result<RatedGame<'a>, SkipReason> {
+    let competition = event.competitions.last().ok_or(SkipReason::NoCompetition)?;
+    if let Some(option) = options.exclusion(event, competition) {
+        return Err(SkipReason::Excluded(option));
+    }
+    if competition.competitors.len() != 2 {
+        return Err(SkipReason::NotTwoCompetitors);
+    }
+    let c_index = competition
+        .competitors
+        .iter()
+        .position(|competitor| competitor.id == team_schedule.team.id)
+        .ok_or(SkipReason::TeamNotListed)?;
+    let competitor = &competition.competitors[c_index];
+    let opponent = &competition.competitors[c_index ^ 1];
+    if !index.counts(&opponent.id) {
+        return Err(SkipReason::OpponentNotCounted);
+    }
+    let (Some(scored), Some(allowed)) = (
+        options.metric_value(competition, competitor),
+        options.metric_value(competition, opponent),
+    ) else {
+        return Err(SkipReason::MissingScore);
+    };
+    // The record, PF/PA, and Pythagorean expectation always follow the final score.
+    let (Some(points_scored), Some(points_allowed)) = (
+        options.points(competition, competitor),
+        options.points(competition, opponent),
+    ) else {
+        return Err(SkipReason::MissingScore);
+    };
+    let (scored, allowed) = match options.home_field_applies(competition) {
+        true => adjust_home_field(
+            scored,
+            allowed,
+            competitor.home_away.as_deref(),
+            options.home_advantage,
+        ),
+        false => (scored, allowed),
+    };
+    let (scored, allowed) =
+        transform_margin(scored, allowed, options.mov_transform, options.mov_cap);
+    let (Some(opponent_schedule), Some(opponent_sums)) = (
+        index.schedules_by_id.get(opponent.id.as_str()),
+        game_sums.get(opponent.id.as_str()),
+    ) else {
+        return Err(SkipReason::MissingOpponentSchedule);
+    };
+    // The opponent's games against this team are what's being rated, so they're left out.
+    let opponent_sums = match opponent_sums.by_opponent.get(&team_schedule.team.id) {
+        Some(head_to_head) => opponent_sums.total.without(head_to_head),
+        None => opponent_sums.total,
+    };
+    if opponent_sums.count < options.min_opponent_games as usize {
+        return Err(SkipReason::FewOpponentGames(opponent_sums.count));
+    }
+    Ok(RatedGame {
+        competition,
+        opponent,
+        opponent_schedule,
+        scored,
+        allowed,
+        points_scored,
+        points_allowed,
+        opponent_sums,
+    })
+}
+
+fn rate_team(
+    team_schedule: &TeamSchedule,
+    index: &ScheduleIndex,
+    game_sums: &HashMap<&str, TeamGameSums>,
+    adjustments: &HashMap<String, (f64, f64)>,
+    league_average: f64,
+    options: &RatingOptions,
+) -> Option<TeamRating> {
+    let mut defense_rating: f64 = 0.0;
+    let mut offense_rating: f64 = 0.0;
+    let mut count: usize = 0;
+    let mut total_weight: f64 = 0.0;
+    let mut record = Record::default();
+    let mut points_for: f64 = 0.0;
+    let mut points_against: f64 = 0.0;
+    let mut opponent_ids: Vec<String> = vec![];
+    let mut game_ratings: Vec<f64> = vec![];
+    let mut breakdown: Vec<GameContribution> = vec![];
+    let latest_date = team_schedule
+        .events
+        .iter()
+        .filter_map(|event| event.date)
+        .max();
+    for event in rated_events(team_schedule, index, options) {
+        let game = match rated_game(team_schedule, event, index, game_sums, options) {
+            Ok(game) => game,
+            Err(reason) => {
+                trace!(
+                    "not counting a game for {}: {}",
+                    team_schedule.team.name(NameField::Location),
+                    reason
+                );
+                continue;
+            }
+        };
+        let (game_defense, game_offense) = match options.model {
+            Model::OpponentAverage => {
+                let o_count = game.opponent_sums.count as f64;
+                let opponent_avg_allowed = game.opponent_sums.allowed / o_count
+                    - game.opponent_sums.schedule_offense / o_count;
+                let opponent_avg_scored = game.opponent_sums.scored / o_count
+                    + game.opponent_sums.schedule_defense / o_count;
+                (
+                    opponent_avg_scored - game.allowed,
+                    game.scored - opponent_avg_allowed,
+                )
+            }
+            // Holding a good offense below the league average is worth more, as is scoring on a
+            // good defense, so the sum is the margin plus the opponent's previous OVR.
+            Model::RatingDiff => {
+                let (opponent_defense, opponent_offense) = adjustments
+                    .get(&game.opponent.id)
+                    .copied()
+                    .unwrap_or_default();
+                (
+                    league_average - game.allowed + opponent_offense,
+                    game.scored - league_average + opponent_defense,
+                )
+            }
+        };
+
+        let weight = recency_weight(event.date, latest_date, options.recency_halflife)
+            * options.tie_weight(game.competition);
+
+        defense_rating += weight * game_defense;
+        offense_rating += weight * game_offense;
+        game_ratings.push(game_defense + game_offense);
+        breakdown.push(GameContribution {
+            date: event.date,
+            opponent_id: game.opponent.id.clone(),
+            opponent: game
+                .opponent_schedule
+                .team
+                .name(options.name_field)
+                .to_string(),
+            points_scored: game.points_scored,
+            points_allowed: game.points_allowed,
+            weight,
+            defense: weight * game_defense,
+            offense: weight * game_offense,
+            margin: game.scored - game.allowed,
+        });
+        total_weight += weight;
+        count += 1;
+        points_for += game.points_scored;
+        points_against += game.points_allowed;
+        match game.points_scored.total_cmp(&game.points_allowed) {
+            Ordering::Greater => record.wins += 1,
+            Ordering::Less => record.losses += 1,
+            Ordering::Equal => record.ties += 1,
+        }
+        opponent_ids.push(game.opponent.id.clone());
+    }
+
+    if count == 0 || total_weight <= 0.0 {
+        debug!(
+            "leaving {} unrated because none of its games counted",
+            team_schedule.team.name(NameField::Location)
+        );
+        return None;
+    }
+
+    let defense_total = defense_rating;
+    let offense_total = offense_rating;
+    defense_rating /= total_weight;
+    offense_rating /= total_weight;
+    for game in breakdown.iter_mut() {
+        game.weight /= total_weight;
+        game.defense /= total_weight;
+        game.offense /= total_weight;
+    }
+
+    Some(TeamRating {
+        id: team_schedule.team.id.clone(),
+        name: team_schedule.team.name(options.name_field).to_string(),
+        conference_id: team_schedule.team.groups.as_ref().map(|g| g.id.clone()),
+        defense_rating,
+        offense_rating,
+        defense_total,
+        offense_total,
+        total_weight,
+        elo: None,
+        games: count,
+        record,
+        points_for: points_for / count as f64,
+        points_against: points_against / count as f64,
+        consistency: standard_deviation(&game_ratings),
+        pythagorean: pythagorean_expectation(points_for, points_against, options.pyth_exponent),
+        opponent_ids,
+        strength_of_schedule: 0.0,
+        breakdown,
+    })
+}
+
+/// A team's events in schedule order, or with `--last-n-games` only the most recent N that would
+/// be counted. The games `rate_team` skips are left out before the window is taken: ones against a
+/// team outside the league, against a team whose schedule is missing, or against a team with fewer
+/// than `--min-opponent-games` other counted games on its schedule.
+pub fn rated_events<'a>(
+    team_schedule: &'a TeamSchedule,
+    index: &ScheduleIndex,
+    options: &RatingOptions,
+) -> Vec<&'a Event> {
+    let Some(last_n_games) = options.last_n_games else {
+        return team_schedule.events.iter().collect();
+    };
+    let team_id = team_schedule.team.id.as_str();
+    let mut events: Vec<&Event> = team_schedule
+        .events
+        .iter()
+        .filter(|event| {
+            let Some(opponent) = counted_opponent(team_id, event, index, options) else {
+                return false;
+            };
+            let Some(opponent_schedule) = index.schedules_by_id.get(opponent.id.as_str()) else {
+                return false;
+            };
+            let other_games = opponent_schedule
+                .events
+                .iter()
+                .filter(|o_event| {
+                    counted_opponent(&opponent.id, o_event, index, options)
+                        .is_some_and(|o_opponent| o_opponent.id != team_id)
+                })
+                .count();
+            other_games >= options.min_opponent_games as usize
+        })
+        .collect();
+    events.sort_by_key(|event| event.date);
+    let skip = events.len().saturating_sub(last_n_games as usize);
+    events.split_off(skip)
+}
+
+/// The opponent in one of `team_id`'s games, if the game passes the filters and both teams are
+/// scored and counted.
+fn counted_opponent<'a>(
+    team_id: &str,
+    event: &'a Event,
+    index: &ScheduleIndex,
+    options: &RatingOptions,
+) -> Option<&'a Competitor> {
+    let competition = event.competitions.last()?;
+    if !options.includes_game(event, competition) {
+        return None;
+    }
+    let [first, second] = competition.competitors.as_slice() else {
+        return None;
+    };
+    let opponent = match (first.id == team_id, second.id == team_id) {
+        (true, _) => second,
+        (_, true) => first,
+        _ => return None,
+    };
+    let counted =
+        |c: &Competitor| options.metric_value(competition, c).is_some() && index.counts(&c.id);
+    (counted(first) && counted(second)).then_some(opponent)
+}
+
+/// Population standard deviation, so a team with the same margin every game has 0.
+fn standard_deviation(values: &[f64]) -> f64 {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    (values
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>()
+        / n)
+        .sqrt()
+}
+
+fn pythagorean_expectation(points_for: f64, points_against: f64, exponent: f64) -> f64 {
+    let scored = points_for.powf(exponent);
+    let allowed = points_against.powf(exponent);
+    if scored + allowed == 0.0 {
+        return 0.5;
+    }
+    scored / (scored + allowed)
+}
+
+/// Halves a game's weight for every `recency_halflife` days it was played before the team's most
+/// recent game. Games without a date are weighted as if they were the most recent.
+fn recency_weight(
+    date: Option<DateTime<Utc>>,
+    latest_date: Option<DateTime<Utc>>,
+    recency_halflife: Option<f64>,
+) -> f64 {
+    let (Some(halflife), Some(date), Some(latest_date)) = (recency_halflife, date, latest_date)
+    else {
+        return 1.0;
+    };
+    let age_days = (latest_date - date).num_seconds() as f64 / (24.0 * 60.0 * 60.0);
+    0.5_f64.powf(age_days / halflife)
+}
+
+/// Splits the home-field advantage evenly between points scored and points allowed, so that the
+/// home team's margin shrinks by `home_advantage` and the away team's grows by the same amount.
+fn adjust_home_field(
+    scored: f64,
+    allowed: f64,
+    home_away: Option<&str>,
+    home_advantage: f64,
+) -> (f64, f64) {
+    let half = home_advantage / 2.0;
+    match home_away {
+        Some("home") => (scored - half, allowed + half),
+        Some("away") => (scored + half, allowed - half),
+        _ => (scored, allowed),
+    }
+}
+
+/// Reshapes the margin of victory by moving the winning score, so blowouts count for less while
+/// the losing score stays as it was.
+fn transform_margin(
+    scored: f64,
+    allowed: f64,
+    mov_transform: MovTransform,
+    mov_cap: Option<f64>,
+) -> (f64, f64) {
+    let margin = match mov_transform {
+        MovTransform::None => return (scored, allowed),
+        MovTransform::Cap => return cap_margin(scored, allowed, mov_cap),
+        MovTransform::Sqrt => (scored - allowed).abs().sqrt(),
+        MovTransform::Log => (scored - allowed).abs().ln_1p(),
+    };
+    match scored.total_cmp(&allowed) {
+        Ordering::Greater => (allowed + margin, allowed),
+        Ordering::Less => (scored, scored + margin),
+        Ordering::Equal => (scored, allowed),
+    }
+}
+
+/// Pulls the winning score down so that the margin of victory is at most `mov_cap` points.
+fn cap_margin(scored: f64, allowed: f64, mov_cap: Option<f64>) -> (f64, f64) {
+    match mov_cap {
+        Some(cap) if scored - allowed > cap => (allowed + cap, allowed),
+        Some(cap) if allowed - scored > cap => (scored, scored + cap),
+        _ => (scored, allowed),
+    }
+}
+
+/// Bars clear themselves once done, so that nothing is left on the terminal line that the output
+/// is printed after.
+pub fn progress_bar(len: usize, message: &'static str, quiet: bool) -> Result<ProgressBar> {
+    if quiet {
+        return Ok(ProgressBar::hidden());
+    }
+    Ok(ProgressBar::new(len as u64)
+        .with_style(ProgressStyle::with_template(
+            "{msg} {wide_bar} {pos}/{len}",
+        )?)
+        .with_message(message)
+        .with_finish(ProgressFinish::AndClear))
+}
+
+/// At 1000 teams per page this is far beyond any real league, so a larger count is a server glitch.
+pub const MAX_TEAM_PAGES: u64 = 100;
+
+pub async fn get_team_ids(
+    fetcher: &Fetcher,
+    endpoints: &Endpoints,
+    max_concurrency: usize,
+    season: &u16,
+    group: Option<&u16>,
+    quiet: bool,
+) -> Result<Vec<String>> {
+    let teams_url = endpoints.teams(season, group);
+    let page_url = |page_index: u64| format!("{}?limit=1000&page={}", teams_url, page_index);
+
+    let pages = fetch_pages(fetcher, max_concurrency, MAX_TEAM_PAGES, page_url)
+        .await
+        .context("failed to fetch the teams listing")?;
+    let items: Vec<&Ref> = pages.iter().flat_map(|page| &page.items).collect();
+
+    let mut team_ids: Vec<String> = items
+        .par_iter()
+        .progress_with(progress_bar(items.len(), "Extracting team IDs", quiet)?)
+        .filter_map(|item| {
+            let team_id = item
+                .url
+                .rsplit_once('/')
+                .and_then(|(_, last_segment)| last_segment.split_once('?'))
+                .map(|(team_id, _)| team_id)
+                .filter(|team_id| is_valid_team_id(team_id));
+            if team_id.is_none() {
+                debug!("skipping a team reference without a valid ID: {}", item.url);
+            }
+            team_id.map(str::to_string)
+        })
+        .collect();
+
+    // Pages sometimes overlap near their boundaries, and a team listed twice would be fetched and
+    // rated twice.
+    let mut seen: HashSet<String> = HashSet::new();
+    team_ids.retain(|team_id| {
+        let first = seen.insert(team_id.clone());
+        if !first {
+            debug!("skipping team {}, which the teams listing repeats", team_id);
+        }
+        first
+    });
+
+    if team_ids.is_empty() {
+        bail!(
+            "no teams found for sport \"{}\", league \"{}\", season {}{} (the first page listed {} items across {} pages); check the --sport, --league, and --season values",
+            endpoints.sport,
+            endpoints.league,
+            season,
+            match group {
+                Some(group) => format!(", group {}", group),
+                None => String::new(),
+            },
+            pages[0].items.len(),
+            pages[0].page_count,
+        );
+    }
+
+    info!(
+        "found {} teams across {} pages of the teams listing",
+        team_ids.len(),
+        pages.len()
+    );
+
+    Ok(team_ids)
+}
+
+/// Fetches every page of a paginated listing. Pages after the first are fetched concurrently, but
+/// `buffered` yields them in page order, so items keep the order the API lists them in.
+async fn fetch_pages(
+    fetcher: &Fetcher,
+    max_concurrency: usize,
+    max_pages: u64,
+    page_url: impl Fn(u64) -> String,
+) -> Result<Vec<PaginatedItems>> {
+    let first_page = fetcher.fetch_json::<PaginatedItems>(&page_url(1)).await?;
+    let page_count = first_page.page_count.as_u64().unwrap_or(1);
+    if page_count > max_pages {
+        bail!(
+            "the listing reported {} pages (more than the {} allowed), refusing to paginate",
+            page_count,
+            max_pages
+        );
+    }
+
+    let remaining_pages: Vec<PaginatedItems> = stream::iter(2..=page_count)
+        .map(|page_index| {
+            let url = page_url(page_index);
+            async move { fetcher.fetch_json::<PaginatedItems>(&url).await }
+        })
+        .buffered(max_concurrency)
+        .try_collect()
+        .await?;
+
+    // A page that reports a different index or page count than requested means the listing is
+    // shifting underneath us, so bail rather than silently returning a partial or duplicated set.
+    let pages: Vec<PaginatedItems> = std::iter::once(first_page).chain(remaining_pages).collect();
+    let consistent = pages.iter().zip(1..).all(|(page, page_index)| {
+        page.page_index.as_u64() == Some(page_index) && page.page_count.as_u64() == Some(page_count)
+    });
+    if !consistent {
+        bail!(
+            "inconsistent pagination: requested pages 1..={} but got page indices [{}] with page counts [{}]",
+            page_count,
+            pages.iter().map(|page| page.page_index.to_string()).collect::<Vec<_>>().join(", "),
+            pages.iter().map(|page| page.page_count.to_string()).collect::<Vec<_>>().join(", "),
+        );
+    }
+
+    Ok(pages)
+}
+
+/// ESPN team IDs are usually numeric, but some leagues use letters too. IDs end up in URL paths, so
+/// anything else is rejected.
+pub fn is_valid_team_id(team_id: &str) -> bool {
+    !team_id.is_empty()
+        && team_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Days, NaiveDate};
+    use serde_json::{json, Value};
+
+    /// A game on the `day`th day of the season, with the first team at home.
+    fn game(day: u64, home: (&str, u32), away: (&str, u32)) -> Value {
+        let date = NaiveDate::from_ymd_opt(2024, 9, 1).unwrap() + Days::new(day);
+        json!({
+            "date": date.format("%Y-%m-%dT17:00Z").to_string(),
+            "seasonType": {"id": "2"},
+            "week": {"number": day / 7 + 1},
+            "competitions": [{
+                "competitors": [
+                    {"id": home.0, "homeAway": "home", "score": {"value": home.1}},
+                    {"id": away.0, "homeAway": "away", "score": {"value": away.1}},
+                ],
+            }],
+        })
+    }
+
+    /// Each team's schedule as ESPN would list it: every game it played.
+    fn schedules(team_ids: &[&str], games: &[Value]) -> Vec<TeamSchedule> {
+        team_ids
+            .iter()
+            .map(|team_id| {
+                let events: Vec<&Value> = games
+                    .iter()
+                    .filter(|game| {
+                        game["competitions"][0]["competitors"]
+                            .as_array()
+                            .unwrap()
+                            .iter()
+                            .any(|competitor| competitor["id"] == *team_id)
+                    })
+                    .collect();
+                serde_json::from_value(json!({
+                    "team": {"id": team_id, "location": format!("Team {}", team_id)},
+                    "events": events,
+                }))
+                .unwrap()
+            })
+            .collect()
+    }
+
+    /// The command line's defaults.
+    fn options() -> RatingOptions {
+        RatingOptions {
+            model: Model::OpponentAverage,
+            iterations: 1,
+            adjustment_decay: 1.0,
+            mov_cap: None,
+            mov_transform: MovTransform::None,
+            home_advantage: 0.0,
+            neutralize_opponents: false,
+            recency_halflife: None,
+            season_type: SeasonTypeFilter::Regular,
+            week: None,
+            include_all_opponents: false,
+            name_field: NameField::Location,
+            pyth_exponent: 2.37,
+            elo: false,
+            elo_k_factor: 20.0,
+            elo_mov_weight: 1.0,
+            exclude_overtime: false,
+            neutral_only: false,
+            ties: TiePolicy::Count,
+            last_n_games: None,
+            min_opponent_games: 1,
+            metric: Metric::Score,
+            forfeit_policy: ForfeitPolicy::Skip,
+        }
+    }
+
+    /// Rates every team in `team_schedules` as a member of the league.
+    fn rate(team_schedules: &[TeamSchedule], options: &RatingOptions) -> Vec<TeamRating> {
+        let team_ids: Vec<&str> = team_schedules
+            .iter()
+            .map(|team_schedule| team_schedule.team.id.as_str())
+            .collect();
+        compute_ratings(team_schedules, &team_ids, options)
+    }
+
+    fn rating<'a>(team_ratings: &'a [TeamRating], team_id: &str) -> &'a TeamRating {
+        team_ratings
+            .iter()
+            .find(|rating| rating.id == team_id)
+            .unwrap_or_else(|| panic!("team {} wasn't rated", team_id))
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    /// A forfeit between A (home) and B, with ESPN's status name and winner flags.
+    fn forfeit(status_name: &str, winners: (Option<bool>, Option<bool>)) -> Event {
+        let mut game = game(0, ("A", 0), ("B", 0));
+        let competition = &mut game["competitions"][0];
+        competition["status"] = json!({"type": {"name": status_name}});
+        competition["competitors"][0]["winner"] = json!(winners.0);
+        competition["competitors"][1]["winner"] = json!(winners.1);
+        serde_json::from_value(game).unwrap()
+    }
+
+    #[test]
+    fn forfeiting_team() {
+        for (status_name, winners, forfeited_by) in [
+            ("STATUS_FORFEIT_HOME_TEAM", (None, None), Some("A")),
+            ("STATUS_FORFEIT_AWAY_TEAM", (None, None), Some("B")),
+            ("STATUS_FORFEIT", (Some(true), Some(false)), Some("B")),
+            ("STATUS_FORFEIT", (Some(true), None), Some("B")),
+            ("STATUS_FORFEIT", (None, Some(false)), Some("B")),
+            ("STATUS_FORFEIT", (Some(false), Some(true)), Some("A")),
+            ("STATUS_FORFEIT", (None, Some(true)), Some("A")),
+            ("STATUS_FORFEIT", (Some(false), None), Some("A")),
+            ("STATUS_FORFEIT", (None, None), None),
+            ("STATUS_FINAL", (Some(true), Some(false)), None),
+        ] {
+            let event = forfeit(status_name, winners);
+            assert_eq!(
+                event.competitions[0]
+                    .forfeited_by()
+                    .map(|competitor| competitor.id.as_str()),
+                forfeited_by,
+                "{} with winners {:?}",
+                status_name,
+                winners
+            );
+        }
+    }
+
+    #[test]
+    fn forfeit_policy() {
+        let event = forfeit("STATUS_FORFEIT", (None, Some(true)));
+        let competition = &event.competitions[0];
+        let [home, away] = competition.competitors.as_slice() else {
+            unreachable!();
+        };
+
+        let skip = options();
+        assert_eq!(
+            skip.exclusion(&event, competition),
+            Some("--forfeit-policy skip")
+        );
+
+        let count_as_loss = RatingOptions {
+            forfeit_policy: ForfeitPolicy::CountAsLoss,
+            ..options()
+        };
+        assert_eq!(count_as_loss.exclusion(&event, competition), None);
+        assert_eq!(count_as_loss.points(competition, home), Some(0.0));
+        assert_eq!(count_as_loss.points(competition, away), Some(1.0));
+        assert!(!count_as_loss.home_field_applies(competition));
+    }
+
+    #[test]
+    fn round_robin_ratings() {
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(7, ("A", 20), ("C", 10)),
+                game(14, ("B", 20), ("C", 10)),
+            ],
+        );
+        let team_ratings = rate(&team_schedules, &options());
+
+        // A held B to 10 where B scored 20 against C, and scored 30 where C allowed B 10, and so
+        // on for each game.
+        for (team_id, defense, offense) in [("A", 5.0, 10.0), ("B", -5.0, 0.0), ("C", 0.0, -10.0)] {
+            let rating = rating(&team_ratings, team_id);
+            assert_close(rating.defense_rating, defense);
+            assert_close(rating.offense_rating, offense);
+            assert_eq!(rating.games, 2);
+        }
+    }
+
+    #[test]
+    fn points_for_and_against_are_averaged() {
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("A", 35), ("B", 17)),
+                game(7, ("A", 40), ("C", 20)),
+                game(14, ("B", 20), ("C", 10)),
+            ],
+        );
+        let team_ratings = rate(&team_schedules, &options());
+        let a = rating(&team_ratings, "A");
+        assert_close(a.points_for, (35.0 + 40.0) / 2.0);
+        assert_close(a.points_against, (17.0 + 20.0) / 2.0);
+    }
+
+    #[test]
+    fn skips_a_team_without_league_opponents() {
+        let team_schedules = schedules(
+            &["A", "B", "C", "X", "Z"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(7, ("A", 20), ("C", 10)),
+                game(14, ("B", 20), ("C", 10)),
+                game(0, ("X", 42), ("Z", 0)),
+                game(7, ("Z", 3), ("X", 28)),
+            ],
+        );
+        // Z isn't in the league, so X has no game that counts.
+        let team_ratings = compute_ratings(&team_schedules, &["A", "B", "C", "X"], &options());
+
+        assert!(team_ratings.iter().all(|rating| rating.id != "X"));
+        assert_eq!(team_ratings.len(), 3);
+        for rating in &team_ratings {
+            assert!(rating.defense_rating.is_finite() && rating.offense_rating.is_finite());
+        }
+    }
+
+    #[test]
+    fn cache_file_names_are_stable() {
+        let cache = Cache {
+            dir: PathBuf::from("cache"),
+            ttl: Duration::from_secs(3600),
+        };
+        assert_eq!(
+            cache.path("https://example.com/").file_name().unwrap(),
+            "0c8b41cfdcb3c914.json"
+        );
+    }
+
+    #[test]
+    fn skips_a_competition_with_one_competitor() {
+        let mut abandoned = game(21, ("A", 7), ("B", 0));
+        abandoned["competitions"][0]["competitors"]
+            .as_array_mut()
+            .unwrap()
+            .pop();
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(7, ("A", 20), ("C", 10)),
+                game(14, ("B", 20), ("C", 10)),
+                abandoned,
+            ],
+        );
+        let team_ratings = rate(&team_schedules, &options());
+
+        let a = rating(&team_ratings, "A");
+        assert_close(a.defense_rating, 5.0);
+        assert_close(a.offense_rating, 10.0);
+    }
+
+    #[test]
+    fn iterations_converge_on_a_round_robin() {
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(7, ("A", 20), ("C", 10)),
+                game(14, ("B", 20), ("C", 10)),
+            ],
+        );
+        let team_ratings = rate(
+            &team_schedules,
+            &RatingOptions {
+                iterations: 100,
+                ..options()
+            },
+        );
+
+        // Each pass corrects an opponent's averages for the previous pass's rating of the one other
+        // team it played, and the ratings settle at two thirds of their first pass values.
+        for (team_id, defense, offense) in [
+            ("A", 10.0 / 3.0, 20.0 / 3.0),
+            ("B", -10.0 / 3.0, 0.0),
+            ("C", 0.0, -20.0 / 3.0),
+        ] {
+            let rating = rating(&team_ratings, team_id);
+            assert!(
+                (rating.defense_rating - defense).abs() < 0.01
+                    && (rating.offense_rating - offense).abs() < 0.01,
+                "expected {} to settle at {} DEF and {} OFF, got {} and {}",
+                team_id,
+                defense,
+                offense,
+                rating.defense_rating,
+                rating.offense_rating
+            );
+        }
+    }
+
+    #[test]
+    fn mov_cap() {
+        assert_eq!(cap_margin(70.0, 0.0, Some(21.0)), (21.0, 0.0));
+        assert_eq!(cap_margin(0.0, 70.0, Some(21.0)), (0.0, 21.0));
+        assert_eq!(cap_margin(17.0, 14.0, Some(21.0)), (17.0, 14.0));
+        assert_eq!(cap_margin(70.0, 0.0, None), (70.0, 0.0));
+
+        let league = |blowout: u32| {
+            schedules(
+                &["A", "B", "C"],
+                &[
+                    game(0, ("A", blowout), ("B", 0)),
+                    game(7, ("A", 20), ("C", 10)),
+                    game(14, ("B", 20), ("C", 10)),
+                ],
+            )
+        };
+        let capped = rate(
+            &league(70),
+            &RatingOptions {
+                mov_transform: MovTransform::Cap,
+                mov_cap: Some(21.0),
+                ..options()
+            },
+        );
+        let uncapped = rate(&league(21), &options());
+        for team_id in ["A", "B", "C"] {
+            let (capped, uncapped) = (rating(&capped, team_id), rating(&uncapped, team_id));
+            assert_close(capped.defense_rating, uncapped.defense_rating);
+            assert_close(capped.offense_rating, uncapped.offense_rating);
+        }
+    }
+
+    #[test]
+    fn recency_weighting() {
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(7, ("A", 20), ("C", 10)),
+                game(14, ("B", 20), ("C", 10)),
+            ],
+        );
+        let unweighted = rate(&team_schedules, &options());
+        let weighted = rate(
+            &team_schedules,
+            &RatingOptions {
+                recency_halflife: Some(7.0),
+                ..options()
+            },
+        );
+
+        // A's game against B was a week before its last game, so it counts half as much. That game
+        // was worth 10 DEF and 20 OFF, and the one against C nothing.
+        let a = rating(&weighted, "A");
+        assert_close(a.defense_rating, 0.5 * 10.0 / 1.5);
+        assert_close(a.offense_rating, 0.5 * 20.0 / 1.5);
+
+        // Without a half-life every game counts the same.
+        let a = rating(&unweighted, "A");
+        assert_close(a.defense_rating, 10.0 / 2.0);
+        assert_close(a.offense_rating, 20.0 / 2.0);
+    }
+
+    #[test]
+    fn strength_of_schedule() {
+        let team_schedules = schedules(
+            &["A", "B", "C", "D", "E", "X"],
+            &[
+                game(0, ("A", 24), ("B", 17)),
+                game(0, ("C", 14), ("D", 10)),
+                game(7, ("A", 31), ("C", 3)),
+                game(7, ("D", 17), ("E", 14)),
+                game(14, ("B", 24), ("C", 10)),
+                game(14, ("C", 20), ("E", 13)),
+                game(21, ("A", 35), ("X", 7)),
+                game(28, ("B", 28), ("X", 10)),
+            ],
+        );
+        let team_ratings = rate(&team_schedules, &options());
+
+        // X only played the two best teams.
+        let mut by_overall: Vec<&TeamRating> = team_ratings.iter().collect();
+        by_overall.sort_by(|r1, r2| r2.overall_rating().total_cmp(&r1.overall_rating()));
+        assert_eq!(
+            by_overall[..2]
+                .iter()
+                .map(|r| r.id.as_str())
+                .collect::<Vec<_>>(),
+            ["A", "B"]
+        );
+        let toughest = team_ratings
+            .iter()
+            .max_by(|r1, r2| r1.strength_of_schedule.total_cmp(&r2.strength_of_schedule))
+            .unwrap();
+        assert_eq!(toughest.id, "X");
+        assert_close(
+            toughest.strength_of_schedule,
+            (rating(&team_ratings, "A").overall_rating()
+                + rating(&team_ratings, "B").overall_rating())
+                / 2.0,
+        );
+    }
+
+    #[test]
+    fn season_type_filter() {
+        let postseason = |day, home, away| {
+            let mut game = game(day, home, away);
+            game["seasonType"]["id"] = json!("3");
+            game
+        };
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(7, ("A", 20), ("C", 10)),
+                game(14, ("B", 20), ("C", 10)),
+                postseason(100, ("B", 40), ("A", 10)),
+                postseason(107, ("C", 30), ("B", 0)),
+            ],
+        );
+        let rate_season = |season_type| {
+            rate(
+                &team_schedules,
+                &RatingOptions {
+                    season_type,
+                    ..options()
+                },
+            )
+        };
+
+        let regular = rate_season(SeasonTypeFilter::Regular);
+        let a = rating(&regular, "A");
+        assert_eq!(a.record.to_string(), "2-0");
+        assert_close(a.defense_rating, 5.0);
+        assert_close(a.offense_rating, 10.0);
+
+        // B's opponent averages come from its other postseason game too: it scored 0 and allowed
+        // 30 against C.
+        let postseason = rate_season(SeasonTypeFilter::Postseason);
+        let a = rating(&postseason, "A");
+        assert_eq!(a.record.to_string(), "0-1");
+        assert_close(a.defense_rating, 0.0 - 40.0);
+        assert_close(a.offense_rating, 10.0 - 30.0);
+
+        let all = rate_season(SeasonTypeFilter::All);
+        assert_eq!(rating(&all, "A").record.to_string(), "2-1");
+    }
+
+    #[test]
+    fn games_counts_only_rated_games() {
+        // A's game against X, which isn't in the league, isn't counted toward --min-games.
+        let team_schedules = schedules(
+            &["A", "B", "C", "X"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(7, ("A", 20), ("C", 10)),
+                game(14, ("B", 20), ("C", 10)),
+                game(21, ("A", 42), ("X", 0)),
+            ],
+        );
+        let team_ratings = compute_ratings(&team_schedules, &["A", "B", "C"], &options());
+        assert_eq!(rating(&team_ratings, "A").games, 2);
+    }
+
+    #[test]
+    fn pythagorean_expectation_of_a_two_to_one_team() {
+        let expected = 2f64.powf(2.37) / (2f64.powf(2.37) + 1.0);
+        assert!((expected - 0.838).abs() < 0.001);
+        assert_close(pythagorean_expectation(40.0, 20.0, 2.37), expected);
+        assert_close(pythagorean_expectation(0.0, 0.0, 2.37), 0.5);
+
+        // A outscored B and C 50-25 between them.
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(7, ("A", 20), ("C", 15)),
+                game(14, ("B", 25), ("C", 15)),
+            ],
+        );
+        let team_ratings = rate(&team_schedules, &options());
+        assert_close(
+            rating(&team_ratings, "A").pythagorean,
+            pythagorean_expectation(50.0, 25.0, 2.37),
+        );
+        assert_close(rating(&team_ratings, "A").pythagorean, expected);
+    }
+
+    #[test]
+    fn elo_replays_games_in_order() {
+        // Listed out of order, but B beating C is replayed last.
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(14, ("B", 20), ("C", 10)),
+                game(7, ("A", 20), ("C", 10)),
+                game(0, ("A", 30), ("B", 10)),
+            ],
+        );
+        let expected =
+            |elo: f64, opponent_elo: f64| 1.0 / (1.0 + 10_f64.powf((opponent_elo - elo) / 400.0));
+        for elo_mov_weight in [0.0, 1.0] {
+            let options = RatingOptions {
+                elo: true,
+                elo_mov_weight,
+                ..options()
+            };
+            let team_ratings = rate(&team_schedules, &options);
+
+            // The margin of victory scales K, by 1 + ln(1 + margin) at full weight.
+            let k_factor = |margin: f64| 20.0 * (1.0 + elo_mov_weight * margin.ln_1p());
+            let a_over_b = k_factor(20.0) * (1.0 - expected(1500.0, 1500.0));
+            let (a, b) = (1500.0 + a_over_b, 1500.0 - a_over_b);
+            let a_over_c = k_factor(10.0) * (1.0 - expected(a, 1500.0));
+            let (a, c) = (a + a_over_c, 1500.0 - a_over_c);
+            let b_over_c = k_factor(10.0) * (1.0 - expected(b, c));
+            let (b, c) = (b + b_over_c, c - b_over_c);
+            for (team_id, elo) in [("A", a), ("B", b), ("C", c)] {
+                assert_close(rating(&team_ratings, team_id).elo.unwrap(), elo);
+                assert_close(rating(&team_ratings, team_id).overall_rating(), elo);
+            }
+        }
+    }
+
+    #[test]
+    fn exclude_overtime() {
+        let mut overtime = game(21, ("B", 27), ("A", 24));
+        overtime["competitions"][0]["status"] = json!({"type": {"detail": "Final/OT"}});
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(7, ("A", 20), ("C", 10)),
+                game(14, ("B", 20), ("C", 10)),
+                overtime,
+            ],
+        );
+        assert!(team_schedules[0].events[2].competitions[0].is_overtime());
+        assert!(!team_schedules[0].events[0].competitions[0].is_overtime());
+
+        let counted = rate(&team_schedules, &options());
+        assert_eq!(rating(&counted, "A").games, 3);
+
+        // Without the overtime game, every team is rated as in the plain round robin.
+        let excluded = rate(
+            &team_schedules,
+            &RatingOptions {
+                exclude_overtime: true,
+                ..options()
+            },
+        );
+        for (team_id, defense, offense) in [("A", 5.0, 10.0), ("B", -5.0, 0.0), ("C", 0.0, -10.0)] {
+            let rating = rating(&excluded, team_id);
+            assert_eq!(rating.games, 2);
+            assert_close(rating.defense_rating, defense);
+            assert_close(rating.offense_rating, offense);
+        }
+    }
+
+    /// A league of `teams` teams where team `i` hosts teams `i + 1` through `i + games_each`
+    /// (wrapping around, and skipping itself), with scores from a fixed pseudo-random sequence.
+    fn generated_league(teams: usize, games_each: usize) -> (Vec<String>, Vec<Value>) {
+        let team_ids: Vec<String> = (0..teams).map(|i| format!("T{}", i)).collect();
+        let mut seed: u64 = 42;
+        let mut score = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as u32 % 45
+        };
+        let mut games = vec![];
+        for i in 0..teams {
+            for offset in (1..=games_each).filter(|offset| offset % teams != 0) {
+                let opponent = &team_ids[(i + offset) % teams];
+                let day = (7 * offset + i) as u64;
+                games.push(game(day, (&team_ids[i], score()), (opponent, score())));
+            }
+        }
+        (team_ids, games)
+    }
+
+    #[test]
+    fn opponent_lookup_matches_a_linear_scan() {
+        let (team_ids, games) = generated_league(12, 4);
+        let team_ids: Vec<&str> = team_ids.iter().map(String::as_str).collect();
+        let team_schedules = schedules(&team_ids, &games);
+        let team_ratings = rate(&team_schedules, &options());
+
+        // The rating as first written: for every game, scan the whole league for the opponent's
+        // schedule and average its other games.
+        let score = |competitor: &Competitor| score_value(competitor).unwrap();
+        for team_schedule in &team_schedules {
+            let (mut defense, mut offense, mut count) = (0.0, 0.0, 0);
+            for event in &team_schedule.events {
+                let competitors = &event.competitions[0].competitors;
+                let c_index = competitors
+                    .iter()
+                    .position(|c| c.id == team_schedule.team.id)
+                    .unwrap();
+                let (competitor, opponent) = (&competitors[c_index], &competitors[c_index ^ 1]);
+                let mut opponent_schedule = None;
+                for ts in &team_schedules {
+                    if ts.team.id == opponent.id {
+                        opponent_schedule = Some(ts);
+                    }
+                }
+                let (mut o_scored, mut o_allowed, mut o_count) = (0.0, 0.0, 0);
+                for o_event in &opponent_schedule.unwrap().events {
+                    let o_competitors = &o_event.competitions[0].competitors;
+                    let o_index = o_competitors
+                        .iter()
+                        .position(|c| c.id == opponent.id)
+                        .unwrap();
+                    if o_competitors[o_index ^ 1].id == team_schedule.team.id {
+                        continue;
+                    }
+                    o_scored += score(&o_competitors[o_index]);
+                    o_allowed += score(&o_competitors[o_index ^ 1]);
+                    o_count += 1;
+                }
+                defense += o_scored / o_count as f64 - score(opponent);
+                offense += score(competitor) - o_allowed / o_count as f64;
+                count += 1;
+            }
+            let rating = rating(&team_ratings, &team_schedule.team.id);
+            assert_eq!(rating.games, count);
+            assert_close(rating.defense_rating, defense / count as f64);
+            assert_close(rating.offense_rating, offense / count as f64);
+        }
+    }
+
+    #[test]
+    fn rates_more_than_255_games() {
+        // Several seasons' worth of games between the same three teams.
+        let (team_ids, games) = generated_league(3, 200);
+        let team_ids: Vec<&str> = team_ids.iter().map(String::as_str).collect();
+        let team_schedules = schedules(&team_ids, &games);
+        let team_ratings = rate(&team_schedules, &options());
+
+        assert_eq!(team_ratings.len(), 3);
+        for rating in &team_ratings {
+            assert_eq!(rating.games, 268);
+            assert_eq!(
+                rating.record.wins + rating.record.losses + rating.record.ties,
+                268
+            );
+            assert!(rating.defense_rating.is_finite() && rating.defense_rating.abs() < 45.0);
+            assert!(rating.offense_rating.is_finite() && rating.offense_rating.abs() < 45.0);
+        }
+    }
+
+    #[test]
+    fn week_cutoff() {
+        // The games are spread over weeks 2 through 7.
+        let (team_ids, games) = generated_league(8, 5);
+        let team_ids: Vec<&str> = team_ids.iter().map(String::as_str).collect();
+        let team_schedules = schedules(&team_ids, &games);
+        let counted_games = |week| {
+            let team_ratings = rate(&team_schedules, &RatingOptions { week, ..options() });
+            team_ratings
+                .iter()
+                .map(|rating| rating.games)
+                .sum::<usize>()
+        };
+
+        let through_week_4 = counted_games(Some(4));
+        let through_week_7 = counted_games(Some(7));
+        assert!(0 < through_week_4 && through_week_4 < through_week_7);
+        assert_eq!(through_week_7, counted_games(None));
+    }
+
+    #[test]
+    fn no_home_field_advantage_at_neutral_sites() {
+        let neutral = |mut game: Value| {
+            game["competitions"][0]["neutralSite"] = json!(true);
+            game
+        };
+        let round_robin = |wrap: fn(Value) -> Value| {
+            schedules(
+                &["A", "B", "C"],
+                &[
+                    wrap(game(0, ("A", 30), ("B", 10))),
+                    wrap(game(7, ("A", 20), ("C", 10))),
+                    wrap(game(14, ("B", 20), ("C", 10))),
+                ],
+            )
+        };
+        let home_advantage = RatingOptions {
+            home_advantage: 4.0,
+            ..options()
+        };
+
+        let at_home = rate(&round_robin(|game| game), &home_advantage);
+        assert!((rating(&at_home, "A").overall_rating() - 15.0).abs() > 1.0);
+
+        let at_neutral_sites = rate(&round_robin(neutral), &home_advantage);
+        assert!(round_robin(neutral)[0].events[0].competitions[0].neutral_site);
+        for (team_id, defense, offense) in [("A", 5.0, 10.0), ("B", -5.0, 0.0), ("C", 0.0, -10.0)] {
+            let rating = rating(&at_neutral_sites, team_id);
+            assert_close(rating.defense_rating, defense);
+            assert_close(rating.offense_rating, offense);
+        }
+    }
+
+    #[test]
+    fn neutral_only_skips_home_games() {
+        let neutral = |mut game: Value| {
+            game["competitions"][0]["neutralSite"] = json!(true);
+            game
+        };
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                neutral(game(7, ("B", 20), ("C", 10))),
+                neutral(game(100, ("A", 24), ("C", 21))),
+            ],
+        );
+        let team_ratings = rate(
+            &team_schedules,
+            &RatingOptions {
+                neutral_only: true,
+                ..options()
+            },
+        );
+
+        // A and B's game at A's stadium isn't counted for either of them, so neither has another
+        // neutral-site game to rate C's games against.
+        assert_eq!(rating(&team_ratings, "A").games, 1);
+        assert_eq!(rating(&team_ratings, "B").games, 1);
+        assert!(team_ratings.iter().all(|rating| rating.id != "C"));
+    }
+
+    #[test]
+    fn tie_policies() {
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(7, ("A", 20), ("C", 10)),
+                game(14, ("B", 20), ("C", 10)),
+                game(21, ("C", 17), ("A", 17)),
+            ],
+        );
+        let rate_ties = |ties| rate(&team_schedules, &RatingOptions { ties, ..options() });
+
+        // A's games were worth 10, 0 and -7 DEF and 20, 0 and -3 OFF, the tie last.
+        let count = rate_ties(TiePolicy::Count);
+        let a = rating(&count, "A");
+        assert_eq!((a.games, a.record.ties), (3, 1));
+        assert_close(a.defense_rating, (10.0 + 0.0 - 7.0) / 3.0);
+        assert_close(a.offense_rating, (20.0 + 0.0 - 3.0) / 3.0);
+
+        let half = rate_ties(TiePolicy::Half);
+        let a = rating(&half, "A");
+        assert_eq!((a.games, a.record.ties), (3, 1));
+        assert_close(a.defense_rating, (10.0 + 0.0 - 0.5 * 7.0) / 2.5);
+        assert_close(a.offense_rating, (20.0 + 0.0 - 0.5 * 3.0) / 2.5);
+
+        // Leaving the tie out leaves the plain round robin.
+        let exclude = rate_ties(TiePolicy::Exclude);
+        for (team_id, defense, offense) in [("A", 5.0, 10.0), ("B", -5.0, 0.0), ("C", 0.0, -10.0)] {
+            let rating = rating(&exclude, team_id);
+            assert_eq!((rating.games, rating.record.ties), (2, 0));
+            assert_close(rating.defense_rating, defense);
+            assert_close(rating.offense_rating, offense);
+        }
+    }
+
+    #[test]
+    fn weighted_rating() {
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(7, ("A", 20), ("C", 10)),
+                game(14, ("B", 20), ("C", 10)),
+            ],
+        );
+        let team_ratings = rate(&team_schedules, &options());
+
+        // B rates -5 DEF and 0 OFF, and C 0 DEF and -10 OFF.
+        let b = rating(&team_ratings, "B");
+        let c = rating(&team_ratings, "C");
+        assert_close(b.weighted_rating(1.0, 1.0), b.overall_rating());
+        assert_close(b.weighted_rating(1.0, 2.0), -5.0);
+        assert_close(c.weighted_rating(1.0, 2.0), -20.0);
+        assert_close(c.weighted_rating(2.0, 0.5), -5.0);
+    }
+
+    #[test]
+    fn last_n_games() {
+        // Every pair of four teams meets twice, so each team has a six-game schedule.
+        let pairs = [
+            ("A", "B"),
+            ("C", "D"),
+            ("A", "C"),
+            ("B", "D"),
+            ("A", "D"),
+            ("B", "C"),
+        ];
+        let games: Vec<Value> = pairs
+            .iter()
+            .chain(&pairs)
+            .enumerate()
+            .map(|(i, (home, away))| {
+                let day = 7 * (i as u64 / 2);
+                game(day, (home, 20 + 3 * i as u32), (away, 17))
+            })
+            .collect();
+        let team_schedules = schedules(&["A", "B", "C", "D"], &games);
+        assert_eq!(team_schedules[0].events.len(), 6);
+
+        let team_ratings = rate(
+            &team_schedules,
+            &RatingOptions {
+                last_n_games: Some(3),
+                ..options()
+            },
+        );
+        let a = rating(&team_ratings, "A");
+        assert_eq!(a.games, 3);
+        assert_eq!(a.opponent_ids, ["B", "C", "D"]);
+        for rating in &team_ratings {
+            assert_eq!(rating.games, 3);
+        }
+    }
+
+    #[test]
+    fn last_n_games_skips_games_that_would_not_be_rated() {
+        // A's three latest games are against X, which isn't in the league, D, whose schedule is
+        // missing, and E, which played no one else.
+        let team_schedules = schedules(
+            &["A", "B", "C", "E", "X"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(7, ("A", 20), ("C", 10)),
+                game(14, ("B", 20), ("C", 10)),
+                game(21, ("A", 42), ("X", 0)),
+                game(28, ("A", 35), ("D", 3)),
+                game(35, ("A", 28), ("E", 7)),
+            ],
+        );
+        let team_ratings = compute_ratings(
+            &team_schedules,
+            &["A", "B", "C", "D", "E"],
+            &RatingOptions {
+                last_n_games: Some(2),
+                ..options()
+            },
+        );
+
+        let a = rating(&team_ratings, "A");
+        assert_eq!(a.opponent_ids, ["B", "C"]);
+        assert_close(a.defense_rating, 5.0);
+        assert_close(a.offense_rating, 10.0);
+    }
+
+    #[test]
+    fn consistency_of_identical_margins() {
+        assert_eq!(standard_deviation(&[7.0, 7.0, 7.0]), 0.0);
+        assert_close(
+            standard_deviation(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]),
+            2.0,
+        );
+
+        // A beat everyone 30-20 and the rest tied each other 20-20, so each of A's games rates
+        // exactly 10, while B's rate 0 against A and -5 against the others.
+        let team_schedules = schedules(
+            &["A", "B", "C", "D"],
+            &[
+                game(0, ("A", 30), ("B", 20)),
+                game(1, ("C", 20), ("D", 20)),
+                game(7, ("A", 30), ("C", 20)),
+                game(8, ("B", 20), ("D", 20)),
+                game(14, ("D", 20), ("A", 30)),
+                game(15, ("B", 20), ("C", 20)),
+            ],
+        );
+        let team_ratings = rate(&team_schedules, &options());
+        assert_close(rating(&team_ratings, "A").consistency, 0.0);
+        assert_close(rating(&team_ratings, "B").consistency, 50f64.sqrt() / 3.0);
+    }
+
+    #[test]
+    fn team_ids_may_have_letters_but_nothing_unsafe_in_a_path() {
+        for team_id in ["25", "usc", "TX-A_2"] {
+            assert!(is_valid_team_id(team_id), "{:?}", team_id);
+        }
+        for team_id in ["", "a b", "../1", "1?x"] {
+            assert!(!is_valid_team_id(team_id), "{:?}", team_id);
+        }
+    }
+
+    /// Keeps every log message, from every test running at the time, so tests look for their own.
+    struct CapturingLogger;
+
+    static LOGGED: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(vec![]);
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            LOGGED.lock().unwrap().push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn capture_logs() {
+        static LOGGER: CapturingLogger = CapturingLogger;
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(log::LevelFilter::Debug);
+        }
+    }
+
+    #[test]
+    fn logs_games_against_missing_opponents() {
+        capture_logs();
+        let games = [
+            game(0, ("A", 30), ("B", 10)),
+            game(7, ("A", 20), ("C", 10)),
+            game(14, ("B", 20), ("C", 10)),
+            game(21, ("A", 24), ("D", 21)),
+            game(28, ("D", 14), ("B", 13)),
+        ];
+        let league = ["A", "B", "C", "D"];
+        let with_d = compute_ratings(&schedules(&league, &games), &league, &options());
+        assert_eq!(rating(&with_d, "A").games, 3);
+
+        // D is in the league, but its schedule wasn't fetched.
+        let without_d = compute_ratings(&schedules(&league[..3], &games), &league, &options());
+        assert_eq!(rating(&without_d, "A").games, 2);
+        let logged = LOGGED.lock().unwrap();
+        assert!(logged.iter().any(|message| message
+            == "skipping Team A's game against team D, whose schedule was not fetched"));
+    }
+
+    #[test]
+    fn mov_transforms() {
+        let margin = |scored, allowed, mov_transform| {
+            let (scored, allowed) = transform_margin(scored, allowed, mov_transform, Some(21.0));
+            scored - allowed
+        };
+        for (mov_transform, blowout, close) in [
+            (MovTransform::None, 50.0, 10.0),
+            (MovTransform::Sqrt, 50f64.sqrt(), 10f64.sqrt()),
+            (MovTransform::Log, 51f64.ln(), 11f64.ln()),
+            (MovTransform::Cap, 21.0, 10.0),
+        ] {
+            assert_close(margin(60.0, 10.0, mov_transform), blowout);
+            assert_close(margin(20.0, 10.0, mov_transform), close);
+            // Losing by the same margin is the mirror image.
+            assert_close(margin(10.0, 60.0, mov_transform), -blowout);
+            assert_close(margin(10.0, 20.0, mov_transform), -close);
+        }
+
+        // The losing score is kept as it was.
+        assert_eq!(
+            transform_margin(10.0, 60.0, MovTransform::Sqrt, None).0,
+            10.0
+        );
+        assert_eq!(
+            transform_margin(60.0, 10.0, MovTransform::Log, None).1,
+            10.0
+        );
+        assert_eq!(
+            transform_margin(17.0, 17.0, MovTransform::Sqrt, None),
+            (17.0, 17.0)
+        );
+    }
+
+    #[test]
+    fn neutralized_opponent_averages() {
+        let mut neutral = game(14, ("A", 20), ("B", 10));
+        neutral["competitions"][0]["neutralSite"] = json!(true);
+        // B played both of its other games at home, averaging 28.5-18.5.
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("B", 30), ("C", 20)),
+                game(7, ("B", 27), ("C", 17)),
+                neutral,
+            ],
+        );
+        let home_advantage = RatingOptions {
+            home_advantage: 4.0,
+            ..options()
+        };
+
+        let raw = rate(&team_schedules, &home_advantage);
+        let a = rating(&raw, "A");
+        assert_close(a.defense_rating, 28.5 - 10.0);
+        assert_close(a.offense_rating, 20.0 - 18.5);
+
+        // Taking half of the 4 points off each side of B's home games leaves 26.5-20.5.
+        let neutralized = rate(
+            &team_schedules,
+            &RatingOptions {
+                neutralize_opponents: true,
+                ..home_advantage
+            },
+        );
+        let a = rating(&neutralized, "A");
+        assert_close(a.defense_rating, 26.5 - 10.0);
+        assert_close(a.offense_rating, 20.0 - 20.5);
+    }
+
+    #[test]
+    fn breakdown_sums_to_the_rating() {
+        let (team_ids, games) = generated_league(8, 3);
+        let team_ids: Vec<&str> = team_ids.iter().map(String::as_str).collect();
+        let team_schedules = schedules(&team_ids, &games);
+        let team_ratings = rate(
+            &team_schedules,
+            &RatingOptions {
+                recency_halflife: Some(10.0),
+                home_advantage: 3.0,
+                ..options()
+            },
+        );
+
+        assert_eq!(team_ratings.len(), 8);
+        for rating in &team_ratings {
+            assert_eq!(rating.breakdown.len(), rating.games);
+            let sum = |contribution: fn(&GameContribution) -> f64| {
+                rating.breakdown.iter().map(contribution).sum::<f64>()
+            };
+            assert_close(sum(|game| game.weight), 1.0);
+            assert_close(sum(|game| game.defense), rating.defense_rating);
+            assert_close(sum(|game| game.offense), rating.offense_rating);
+        }
+    }
+
+    #[test]
+    fn min_opponent_games() {
+        // Besides playing A, B played three other games and X only one.
+        let team_schedules = schedules(
+            &["A", "B", "C", "D", "X"],
+            &[
+                game(0, ("B", 21), ("C", 14)),
+                game(7, ("D", 10), ("B", 17)),
+                game(14, ("C", 24), ("B", 20)),
+                game(0, ("X", 13), ("D", 6)),
+                game(21, ("A", 28), ("B", 21)),
+                game(28, ("X", 10), ("A", 31)),
+            ],
+        );
+        let opponents = |min_opponent_games| {
+            let team_ratings = rate(
+                &team_schedules,
+                &RatingOptions {
+                    min_opponent_games,
+                    ..options()
+                },
+            );
+            rating(&team_ratings, "A").opponent_ids.clone()
+        };
+        assert_eq!(opponents(1), ["B", "X"]);
+        assert_eq!(opponents(3), ["B"]);
+    }
+
+    #[test]
+    fn score_metric() {
+        let mut unscored = game(21, ("A", 0), ("B", 0));
+        unscored["competitions"][0]["competitors"][1]
+            .as_object_mut()
+            .unwrap()
+            .remove("score");
+        let event: Event = serde_json::from_value(unscored.clone()).unwrap();
+        let [home, away] = event.competitions[0].competitors.as_slice() else {
+            unreachable!();
+        };
+        assert_eq!(Metric::Score.value(home), Some(0.0));
+        assert_eq!(Metric::Score.value(away), None);
+
+        // A game without a score for both sides doesn't count.
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(7, ("A", 20), ("C", 10)),
+                game(14, ("B", 20), ("C", 10)),
+                unscored,
+            ],
+        );
+        let team_ratings = rate(&team_schedules, &options());
+        let a = rating(&team_ratings, "A");
+        assert_eq!(a.games, 2);
+        assert_close(a.defense_rating, 5.0);
+        assert_close(a.offense_rating, 10.0);
+    }
+
+    #[test]
+    fn skips_a_competition_without_the_team() {
+        capture_logs();
+        let mut team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(7, ("A", 20), ("C", 10)),
+                game(14, ("B", 20), ("C", 10)),
+            ],
+        );
+        // A game between C and somebody else, listed in A's and B's schedules by mistake.
+        for team_schedule in &mut team_schedules[..2] {
+            let mismatched = serde_json::from_value(game(21, ("C", 50), ("X", 0))).unwrap();
+            team_schedule.events.push(mismatched);
+        }
+        let team_ratings = rate(&team_schedules, &options());
+
+        for (team_id, defense, offense) in [("A", 5.0, 10.0), ("B", -5.0, 0.0), ("C", 0.0, -10.0)] {
+            let rating = rating(&team_ratings, team_id);
+            assert_eq!(rating.games, 2);
+            assert_close(rating.defense_rating, defense);
+            assert_close(rating.offense_rating, offense);
+        }
+        assert!(LOGGED.lock().unwrap().iter().any(|message| message
+            == "skipping a game in Team A's schedule that doesn't list them among its competitors (C vs. X)"));
+    }
+
+    #[test]
+    fn game_sums_without_an_opponent_match_a_rescan() {
+        // Teams meet more than once, so taking an opponent out removes several games.
+        let (team_ids, games) = generated_league(5, 7);
+        let team_ids: Vec<&str> = team_ids.iter().map(String::as_str).collect();
+        let team_schedules = schedules(&team_ids, &games);
+        let options = options();
+        let index = ScheduleIndex::new(&team_schedules, &team_ids, &options);
+
+        for team_schedule in &team_schedules {
+            let sums = team_game_sums(team_schedule, &index, &HashMap::new(), &options);
+            assert_eq!(sums.total.count, team_schedule.events.len());
+            for (opponent_id, head_to_head) in &sums.by_opponent {
+                let without = sums.total.without(head_to_head);
+                let (mut scored, mut allowed, mut count) = (0.0, 0.0, 0);
+                for event in &team_schedule.events {
+                    let competitors = &event.competitions[0].competitors;
+                    let c_index = competitors
+                        .iter()
+                        .position(|c| c.id == team_schedule.team.id)
+                        .unwrap();
+                    if competitors[c_index ^ 1].id == *opponent_id {
+                        continue;
+                    }
+                    scored += score_value(&competitors[c_index]).unwrap();
+                    allowed += score_value(&competitors[c_index ^ 1]).unwrap();
+                    count += 1;
+                }
+                assert_eq!(without.count, count);
+                assert_close(without.scored, scored);
+                assert_close(without.allowed, allowed);
+            }
+        }
+    }
+
+    #[test]
+    fn audit_skip_reasons() {
+        let counted = game(0, ("A", 21), ("B", 14));
+        let mut no_competition = game(1, ("A", 0), ("B", 0));
+        no_competition["competitions"] = json!([]);
+        let mut postseason = game(2, ("A", 0), ("B", 0));
+        postseason["seasonType"]["id"] = json!("3");
+        let mut one_competitor = game(3, ("A", 0), ("B", 0));
+        one_competitor["competitions"][0]["competitors"]
+            .as_array_mut()
+            .unwrap()
+            .pop();
+        let mut unscored = game(6, ("A", 0), ("B", 0));
+        unscored["competitions"][0]["competitors"][1]
+            .as_object_mut()
+            .unwrap()
+            .remove("score");
+        let a_games = [
+            counted.clone(),
+            no_competition,
+            postseason,
+            one_competitor,
+            game(4, ("C", 10), ("X", 7)),
+            game(5, ("A", 45), ("Z", 0)),
+            unscored,
+            game(7, ("A", 17), ("D", 3)),
+            game(8, ("C", 13), ("A", 10)),
+        ];
+        let mut team_schedules = schedules(
+            &["B", "C", "E"],
+            &[
+                counted,
+                game(3, ("B", 24), ("E", 20)),
+                game(8, ("C", 13), ("A", 10)),
+            ],
+        );
+        team_schedules.push(
+            serde_json::from_value(json!({
+                "team": {"id": "A", "location": "Team A"},
+                "events": a_games,
+            }))
+            .unwrap(),
+        );
+        let league = ["A", "B", "C", "D", "E"];
+        let options = options();
+        let index = ScheduleIndex::new(&team_schedules, &league, &options);
+        let game_sums: HashMap<&str, TeamGameSums> = team_schedules
+            .iter()
+            .map(|team_schedule| {
+                (
+                    team_schedule.team.id.as_str(),
+                    team_game_sums(team_schedule, &index, &HashMap::new(), &options),
+                )
+            })
+            .collect();
+
+        let a = &team_schedules[3];
+        let reasons: Vec<String> = a
+            .events
+            .iter()
+            .map(
+                |event| match rated_game(a, event, &index, &game_sums, &options) {
+                    Ok(_) => "counted".to_string(),
+                    Err(reason) => reason.to_string(),
+                },
+            )
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                "counted",
+                "no competition listed",
+                "excluded by --season-type",
+                "doesn't have exactly two competitors",
+                "team isn't among the competitors",
+                "opponent isn't in the league (see --include-all-opponents)",
+                "missing score",
+                "opponent's schedule wasn't fetched",
+                "opponent has only 0 other counted games (see --min-opponent-games)",
+            ]
+        );
+        assert_eq!(
+            SkipReason::NotRecent(3).to_string(),
+            "not among the 3 most recent games (--last-n-games)"
+        );
+    }
+
+    #[test]
+    fn consistent_league_has_no_prediction_error() {
+        // A is 10 points better than B, which is 10 points better than C, in every game.
+        let team_schedules = schedules(
+            &["A", "B", "C"],
+            &[
+                game(0, ("A", 30), ("B", 20)),
+                game(7, ("A", 30), ("C", 10)),
+                game(14, ("B", 20), ("C", 10)),
+            ],
+        );
+        let options = RatingOptions {
+            iterations: 100,
+            ..options()
+        };
+        let team_ratings = rate(&team_schedules, &options);
+
+        let error = prediction_error(&team_ratings, 1.0, 1.0).unwrap();
+        assert_eq!(error.games, 3);
+        assert!(
+            error.mean_absolute_error < 0.01,
+            "expected near-zero error, got {}",
+            error.mean_absolute_error
+        );
+
+        // With both weights at zero every prediction is an even game, so the error is the average
+        // margin.
+        let error = prediction_error(&team_ratings, 0.0, 0.0).unwrap();
+        assert_close(error.mean_absolute_error, 40.0 / 3.0);
+    }
+
+    #[test]
+    fn schedule_index_counts_league_teams_unless_all_opponents_count() {
+        let team_schedules = schedules(&["A", "B"], &[game(0, ("A", 21), ("X", 14))]);
+        let index = ScheduleIndex::new(&team_schedules, &["A", "B"], &options());
+        assert!(index.counts("A"));
+        assert!(!index.counts("X"));
+        assert!(index.schedules_by_id.contains_key("B"));
+
+        let all_opponents = RatingOptions {
+            include_all_opponents: true,
+            ..options()
+        };
+        let index = ScheduleIndex::new(&team_schedules, &["A", "B"], &all_opponents);
+        assert!(index.counts("X"));
+    }
+
+    #[test]
+    fn blank_names_fall_back() {
+        let team = |value: Value| -> Team { serde_json::from_value(value).unwrap() };
+        let named = team(json!({"id": "1", "location": "Iowa", "displayName": "Iowa Hawkeyes"}));
+        assert_eq!(named.name(NameField::Location), "Iowa");
+
+        let no_location = team(json!({"id": "1", "location": "", "displayName": "Iowa Hawkeyes"}));
+        assert_eq!(no_location.name(NameField::Location), "Iowa Hawkeyes");
+
+        let unnamed = team(json!({"id": "1", "location": " "}));
+        assert_eq!(unnamed.name(NameField::Location), "1");
+        assert_eq!(unnamed.name(NameField::Abbreviation), "1");
+    }
+
+    #[test]
+    fn rating_diff_solves_least_squares() {
+        // Teams play different numbers of games, so the fixed point isn't just a margin average.
+        let team_schedules = schedules(
+            &["A", "B", "C", "D"],
+            &[
+                game(0, ("A", 30), ("B", 10)),
+                game(1, ("C", 13), ("D", 14)),
+                game(7, ("A", 20), ("C", 10)),
+                game(8, ("B", 24), ("D", 21)),
+                game(14, ("D", 7), ("A", 35)),
+                game(21, ("B", 20), ("C", 10)),
+                game(28, ("B", 17), ("D", 3)),
+            ],
+        );
+        let options = RatingOptions {
+            model: Model::RatingDiff,
+            iterations: 1000,
+            ..options()
+        };
+        let team_ratings = rate(&team_schedules, &options);
+        let overall: HashMap<&str, f64> = team_ratings
+            .iter()
+            .map(|rating| (rating.id.as_str(), rating.overall_rating()))
+            .collect();
+
+        // At the least-squares solution each team's residuals, actual margin minus the difference
+        // in ratings, cancel out.
+        for rating in &team_ratings {
+            let residual: f64 = rating
+                .breakdown
+                .iter()
+                .map(|game| {
+                    game.margin - (overall[rating.id.as_str()] - overall[game.opponent_id.as_str()])
+                })
+                .sum();
+            assert!(
+                residual.abs() < 0.01,
+                "{} has residual {}",
+                rating.id,
+                residual
+            );
+        }
+
+        let more_passes = rate(
+            &team_schedules,
+            &RatingOptions {
+                iterations: 2000,
+                ..options
+            },
+        );
+        for rating in &more_passes {
+            assert!((rating.overall_rating() - overall[rating.id.as_str()]).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn retry_after_in_seconds_or_as_a_date() {
+        assert_eq!(parse_retry_after("1"), Some(Duration::from_secs(1)));
+        assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        let later = (Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        let delay = parse_retry_after(&later).unwrap();
+        assert!(delay > Duration::from_secs(25) && delay <= Duration::from_secs(30));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn only_unreachable_servers_are_transient_without_a_status() {
+        let client = Client::new();
+        // Nothing listens on port 1, so connecting fails.
+        let unreachable = client.get("http://127.0.0.1:1").send().await.unwrap_err();
+        assert!(is_transient(&unreachable), "{}", unreachable);
+        let malformed = client.get("http://").send().await.unwrap_err();
+        assert!(!is_transient(&malformed), "{}", malformed);
+    }
+}
@@ -1,398 +1,3425 @@
 // SPDX-FileCopyrightText: 2024 Sebastian Rasor <https://www.sebastianrasor.com/contact>
 // SPDX-License-Identifier: AGPL-3.0-only
 
-use anyhow::Result;
-use clap::Parser;
-use futures::{stream, StreamExt};
-use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{Datelike, Local, NaiveDate, SecondsFormat, Utc};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use console::Term;
+use futures::{stream, StreamExt, TryStreamExt};
+use indicatif::ParallelProgressIterator;
+use log::{debug, info, LevelFilter};
+use rasor_ratings::{
+    compute_ratings_with_progress, is_valid_team_id, prediction_error, progress_bar, rated_events,
+    rated_game, team_game_sums, Cache, Competitor, Endpoints, EspnProvider, Fetcher, ForfeitPolicy,
+    Group, Metric, Model, MovTransform, NameField, Ranking, Rankings, RatingOptions, ScheduleIndex,
+    ScheduleProvider, SeasonTypeFilter, SkipReason, Team, TeamDetails, TeamGameSums, TeamRating,
+    TeamSchedule, TiePolicy, CORE_API_BASE, ELO_INITIAL_RATING, SITE_API_BASE,
+};
 use rayon::prelude::*;
-use reqwest::Client;
-use serde::Deserialize;
-use serde_json::Number;
-use tabled::settings::Style;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Proxy};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tabled::builder::Builder;
+use tabled::settings::object::Columns;
+use tabled::settings::themes::Theme;
+use tabled::settings::{Alignment, Color, Style, Width};
 use tabled::{Table, Tabled};
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    after_help = "--sport, --league, and --season go before the command name; other options can go before or after it.
+
+Exit status:
+  0  success, using every team's schedule
+  1  error, including any failed fetch with --strict
+  2  invalid arguments
+  3  success, but some teams were dropped because their schedules could not be fetched
+  4  interrupted while fetching; the ratings use only the schedules fetched so far"
+)]
 struct Args {
-    #[arg(short = 'c', long, default_value_t = 8)]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(
+        short = 'c',
+        long,
+        env = "RASOR_MAX_CONCURRENCY",
+        default_value_t = 8,
+        global = true
+    )]
     max_concurrency: usize,
 
-    #[arg(short = 's', long)]
+    #[arg(short = 's', long, env = "RASOR_SPORT")]
     sport: String,
 
-    #[arg(short, long)]
+    #[arg(short, long, env = "RASOR_LEAGUE")]
     league: String,
 
-    #[arg(short = 'S', long)]
+    /// Season year, or `auto` for the season in progress (or most recently finished) today
+    #[arg(short = 'S', long = "season", value_name = "YEAR", env = "RASOR_SEASON", default_value = "auto", value_parser = parse_season)]
+    season_arg: Season,
+
+    /// Month that seasons start in for `--season auto` [default: per sport, e.g. 8 for football]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=12), global = true)]
+    season_start_month: Option<u32>,
+
+    /// The season being rated, resolved from --season
+    #[arg(skip)]
     season: u16,
 
-    #[arg(short, long)]
-    group: Option<u16>,
+    /// Only rate teams in this group (e.g. a conference); repeat to combine several
+    #[arg(short, long, action = ArgAction::Append, global = true)]
+    group: Vec<u16>,
 
-    #[arg(short, long)]
-    top: Option<usize>,
+    /// ESPN season type that --group team lists and conference names come from: 1 preseason, 2
+    /// regular season, 3 postseason
+    #[arg(long, value_name = "CODE", default_value_t = 2, global = true)]
+    season_type_code: u16,
 
-    #[arg(short, long, default_value_t = false)]
+    /// Show only the first N teams, or the first N percent of them with a trailing % (e.g. 25%)
+    #[arg(short, long, value_name = "N", value_parser = parse_top, global = true)]
+    top: Option<Top>,
+
+    #[arg(short, long, default_value_t = false, global = true)]
     reverse: bool,
 
-    #[arg(short, long, default_value_t = false, conflicts_with("offense"))]
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        conflicts_with("offense"),
+        global = true
+    )]
     defense: bool,
 
-    #[arg(short, long, default_value_t = false, conflicts_with("defense"))]
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        conflicts_with("defense"),
+        global = true
+    )]
     offense: bool,
+
+    #[arg(long, default_value_t = false, conflicts_with_all(["defense", "offense"]), global = true)]
+    sos: bool,
+
+    /// List teams alphabetically, still numbered by their rank
+    #[arg(long, default_value_t = false, conflicts_with_all(["defense", "offense", "sos"]), global = true)]
+    alphabetical: bool,
+
+    /// Sort by a column (#, team, ovr, def, off, sos, pyth, pf, pa, cons), descending by default for
+    /// ratings and ascending for # and team; --defense, --offense, --sos, and --alphabetical are
+    /// shortcuts
+    #[arg(
+        long,
+        value_name = "COLUMN[:asc|desc]",
+        value_parser = parse_sort_key,
+        conflicts_with_all(["defense", "offense", "sos", "alphabetical"]),
+        global = true
+    )]
+    sort: Option<SortKey>,
+
+    #[arg(short = 'f', long, visible_alias = "format", value_enum, default_value_t = OutputFormat::Table, global = true)]
+    output_format: OutputFormat,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with("output_format"),
+        global = true
+    )]
+    json: bool,
+
+    /// Emit the JSON teams as a bare array, without the metadata wrapper
+    #[arg(long, default_value_t = false, requires("json"), global = true)]
+    json_flat: bool,
+
+    #[arg(long, default_value_t = 12, global = true)]
+    cache_ttl: u64,
+
+    /// Days to reuse the league's list of team IDs, which changes far less often than schedules
+    #[arg(long, default_value_t = 7, global = true)]
+    teams_cache_ttl: u64,
+
+    #[arg(long, default_value_t = false, global = true)]
+    no_cache: bool,
+
+    #[arg(long, default_value_t = 3, global = true)]
+    max_retries: u32,
+
+    #[arg(long, default_value_t = false, global = true)]
+    strict: bool,
+
+    #[arg(long, default_value_t = 30, global = true)]
+    timeout: u64,
+
+    /// How game results become DEF and OFF
+    #[arg(long, value_enum, default_value_t = Model::OpponentAverage, global = true)]
+    model: Model,
+
+    /// Maximum passes over the schedules, stopping early once ratings converge [default: 1, or 100
+    /// with --model rating-diff]
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..), global = true)]
+    iterations: Option<u32>,
+
+    /// Correct opponent averages for their own schedules this many levels deep, weighting each
+    /// level by --adjust-decay times the one above it; 0 rates by margin alone and 1 is the default
+    #[arg(long, value_name = "N", conflicts_with_all(["iterations", "model"]), global = true)]
+    adjust_depth: Option<u32>,
+
+    /// Weight of each level of --adjust-depth relative to the level above it, from 0 to 1
+    #[arg(long, default_value_t = 0.5, value_parser = parse_decay, requires = "adjust_depth", global = true)]
+    adjust_decay: f64,
+
+    #[arg(long, required_if_eq("mov_transform", "cap"), global = true)]
+    mov_cap: Option<f64>,
+
+    /// Stat compared between opponents in each game
+    #[arg(long, value_enum, default_value_t = Metric::Score, global = true)]
+    metric: Metric,
+
+    /// Dampen margins of victory; defaults to cap when --mov-cap is given and none otherwise
+    #[arg(long, value_enum, global = true)]
+    mov_transform: Option<MovTransform>,
+
+    /// Points subtracted from the home team's margin in non-neutral-site games
+    #[arg(long, default_value_t = 0.0, global = true)]
+    home_advantage: f64,
+
+    /// Also remove --home-advantage from opponents' other games, so their averages are
+    /// location-neutral
+    #[arg(long, default_value_t = false, conflicts_with = "elo", global = true)]
+    neutralize_opponents: bool,
+
+    #[arg(long, value_name = "DAYS", value_parser = parse_halflife, global = true)]
+    recency_halflife: Option<f64>,
+
+    #[arg(long, value_enum, default_value_t = SeasonTypeFilter::Regular, global = true)]
+    season_type: SeasonTypeFilter,
+
+    /// Only count regular season games played through this week
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), conflicts_with("season_type"), global = true)]
+    week: Option<u32>,
+
+    /// Count games against opponents outside the fetched teams (e.g. non-FBS), which can pull in
+    /// teams with very sparse schedules
+    #[arg(long, default_value_t = false, global = true)]
+    include_all_opponents: bool,
+
+    #[arg(long, default_value_t = 1, global = true)]
+    min_games: usize,
+
+    /// Skip games against opponents with fewer than this many other counted games, whose averages
+    /// are too noisy to adjust against
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..), global = true)]
+    min_opponent_games: u32,
+
+    #[arg(long, value_enum, default_value_t = NameField::Location, global = true)]
+    name_field: NameField,
+
+    #[arg(long, value_name = "A,B", value_parser = parse_team_pair, global = true)]
+    compare: Option<(String, String)>,
+
+    /// List every game in this team's (by ID or name) schedule with whether it counted toward the
+    /// rating, and if not why, instead of rating teams
+    #[arg(
+        long,
+        value_name = "ID_OR_NAME",
+        conflicts_with_all(["team", "compare", "elo", "json", "compact", "conference_ratings", "group_by_conference"]),
+        global = true
+    )]
+    audit: Option<String>,
+
+    /// Show only this team (by ID or name), with each counted game's contribution to its rating
+    #[arg(
+        long,
+        value_name = "ID_OR_NAME",
+        conflicts_with_all(["compare", "elo", "normalize", "compact", "group_by_conference"]),
+        global = true
+    )]
+    team: Option<String>,
+
+    /// After the table, list how each team's DEF, OFF, and OVR follow from its counted games
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all(["elo", "normalize", "compact", "conference_ratings", "group_by_conference"]),
+        global = true
+    )]
+    explain: bool,
+
+    #[arg(long, default_value_t = false, conflicts_with_all(["json", "output_format"]), global = true)]
+    group_by_conference: bool,
+
+    /// Print one line per team, like `1. Georgia (+14.23)`, instead of a table
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all(["json", "output_format", "group_by_conference"]),
+        global = true
+    )]
+    compact: bool,
+
+    /// Rank conferences by the average overall rating of their teams instead of listing teams
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all([
+            "json",
+            "output_format",
+            "group_by_conference",
+            "compact",
+            "team",
+            "compare",
+            "top"
+        ]),
+        global = true
+    )]
+    conference_ratings: bool,
+
+    #[arg(long, default_value_t = 2.37, global = true)]
+    pyth_exponent: f64,
+
+    #[arg(long, default_value_t = false, global = true)]
+    exclude_overtime: bool,
+
+    /// Only count games played at neutral sites, such as bowl games
+    #[arg(long, default_value_t = false, global = true)]
+    neutral_only: bool,
+
+    /// Only count each team's most recent N games
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), conflicts_with("elo"), global = true)]
+    last_n_games: Option<u32>,
+
+    /// How tied games count toward ratings and records: fully, at half weight, or not at all
+    #[arg(long, value_enum, default_value_t = TiePolicy::Count, global = true)]
+    ties: TiePolicy,
+
+    /// Whether forfeits are left out or count as a 1-0 loss for the team that forfeited
+    #[arg(long, value_enum, default_value_t = ForfeitPolicy::Skip, global = true)]
+    forfeit_policy: ForfeitPolicy,
+
+    /// Rate teams with Elo instead of opponent-adjusted margins
+    #[arg(long, default_value_t = false, global = true)]
+    elo: bool,
+
+    #[arg(long, default_value_t = 20.0, global = true)]
+    elo_k_factor: f64,
+
+    /// Scales the K-factor by `1 + weight * ln(1 + margin)`; 0 ignores the margin of victory
+    #[arg(long, default_value_t = 1.0, global = true)]
+    elo_mov_weight: f64,
+
+    #[arg(long, default_value_t = 1.0, conflicts_with("elo"), global = true)]
+    defense_weight: f64,
+
+    #[arg(long, default_value_t = 1.0, conflicts_with("elo"), global = true)]
+    offense_weight: f64,
+
+    #[arg(short, long, default_value_t = false, global = true)]
+    quiet: bool,
+
+    /// Log progress to stderr (-v), along with skipped data (-vv) and every request (-vvv);
+    /// RUST_LOG replaces these levels when set
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    #[arg(long, default_value = "psql", value_parser = table_style, global = true)]
+    style: Theme,
+
+    /// Fit the table within this many columns by truncating team names [default: the terminal's
+    /// width, or unlimited when not writing to a terminal]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..), global = true)]
+    width: Option<u16>,
+
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto, global = true)]
+    color: ColorChoice,
+
+    #[arg(long, default_value_t = 2, global = true)]
+    precision: usize,
+
+    /// Give teams whose OVR is equal at --precision the same rank (1, 2, 2, 4)
+    #[arg(long, default_value_t = false, global = true)]
+    tie_ranks: bool,
+
+    #[arg(long, default_value_t = false, global = true)]
+    stats: bool,
+
+    /// Report how closely differences in OVR predicted the margins of the counted games
+    #[arg(long, default_value_t = false, conflicts_with = "elo", global = true)]
+    validate: bool,
+
+    /// Print how long team discovery, schedule fetching, and rating took to stderr
+    #[arg(long, default_value_t = false, global = true)]
+    timings: bool,
+
+    /// Show the standard deviation of each team's per-game rating (CONS); lower is more consistent
+    #[arg(long, default_value_t = false, global = true)]
+    show_consistency: bool,
+
+    /// Rescale OVR, DEF, and OFF to 0-100 from lowest to highest, or to z-scores
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "range", global = true)]
+    normalize: Option<Normalization>,
+
+    /// Show each team's ESPN ID, for joining the output against other data
+    #[arg(long, default_value_t = false, global = true)]
+    show_id: bool,
+
+    /// Show each team's average points scored (PF) and allowed (PA) per counted game
+    #[arg(long, default_value_t = false, global = true)]
+    show_points: bool,
+
+    /// Show each team's wins over opponents rated above --quality-threshold (QW)
+    #[arg(long, default_value_t = false, global = true)]
+    show_quality_wins: bool,
+
+    /// OVR an opponent must be rated above for a win over it to be a quality win [default: 0, or
+    /// the initial Elo rating with --elo]
+    #[arg(long, requires = "show_quality_wins", global = true)]
+    quality_threshold: Option<f64>,
+
+    #[arg(long, conflicts_with("dump_team_ids"), global = true)]
+    team_ids_file: Option<PathBuf>,
+
+    /// An earlier run's JSON output, or its CSV or TSV output with --show-id, to show each team's
+    /// rank change (Δ) against
+    #[arg(long, global = true)]
+    baseline: Option<PathBuf>,
+
+    #[arg(long, default_value_t = false, global = true)]
+    dump_team_ids: bool,
+
+    /// Print the number of teams and the schedule URLs that would be fetched, then exit
+    #[arg(long, default_value_t = false, global = true)]
+    dry_run: bool,
+
+    #[arg(long, default_value = USER_AGENT, global = true)]
+    user_agent: String,
+
+    #[arg(long = "header", value_name = "KEY=VALUE", value_parser = parse_header, global = true)]
+    headers: Vec<(HeaderName, HeaderValue)>,
+
+    /// Proxy for all requests; defaults to the HTTPS_PROXY/ALL_PROXY environment variables
+    #[arg(long, value_name = "URL", value_parser = parse_proxy, global = true)]
+    proxy: Option<Proxy>,
+
+    /// Don't ask for gzip or deflate compressed responses, for proxies that mangle them
+    #[arg(long, default_value_t = false, global = true)]
+    no_compression: bool,
+
+    /// Print the data sources and rating methodology for these options, then exit
+    #[arg(long, default_value_t = false, global = true)]
+    about_data: bool,
+
+    #[arg(long, default_value = SITE_API_BASE, global = true)]
+    site_api_base: String,
+
+    #[arg(long, default_value = CORE_API_BASE, global = true)]
+    core_api_base: String,
+
+    /// Write the output to this file instead of stdout, creating parent directories as needed
+    #[arg(long, value_name = "PATH", global = true)]
+    output: Option<PathBuf>,
+
+    /// Append every rated team to the `ratings` table of this SQLite database
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "PATH", global = true)]
+    sqlite: Option<PathBuf>,
+
+    /// Compare the ranking against an ESPN poll, reporting Spearman's rank correlation
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "ap",
+        conflicts_with_all(["compare", "json", "output_format"]),
+        global = true
+    )]
+    compare_poll: Option<Poll>,
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct Ref {
-    #[serde(rename = "$ref")]
-    url: String,
+const RATING_DIFF_ITERATIONS: u32 = 100;
+
+impl Args {
+    /// Rating differences only mean anything once they've converged, so that model gets more
+    /// passes by default.
+    fn iterations(&self) -> u32 {
+        // Each pass corrects for one more level of opponents' schedules.
+        if let Some(depth) = self.adjust_depth {
+            return depth.max(1);
+        }
+        self.iterations.unwrap_or(match self.model {
+            Model::OpponentAverage => 1,
+            Model::RatingDiff => RATING_DIFF_ITERATIONS,
+        })
+    }
+
+    /// A single rating-difference pass, with no previous ratings to add, is the margin alone.
+    fn model(&self) -> Model {
+        match self.adjust_depth {
+            Some(0) => Model::RatingDiff,
+            _ => self.model,
+        }
+    }
+
+    /// How much each pass's correction counts relative to the one before it.
+    fn adjustment_decay(&self) -> f64 {
+        match self.adjust_depth {
+            Some(_) => self.adjust_decay,
+            None => 1.0,
+        }
+    }
+
+    fn mov_transform(&self) -> MovTransform {
+        match (self.mov_transform, self.mov_cap) {
+            (Some(mov_transform), _) => mov_transform,
+            (None, Some(_)) => MovTransform::Cap,
+            (None, None) => MovTransform::None,
+        }
+    }
+
+    fn rating_options(&self) -> RatingOptions {
+        RatingOptions {
+            model: self.model(),
+            iterations: self.iterations(),
+            adjustment_decay: self.adjustment_decay(),
+            mov_cap: self.mov_cap,
+            mov_transform: self.mov_transform(),
+            home_advantage: self.home_advantage,
+            neutralize_opponents: self.neutralize_opponents,
+            recency_halflife: self.recency_halflife,
+            season_type: self.season_type,
+            week: self.week,
+            include_all_opponents: self.include_all_opponents,
+            name_field: self.name_field,
+            pyth_exponent: self.pyth_exponent,
+            elo: self.elo,
+            elo_k_factor: self.elo_k_factor,
+            elo_mov_weight: self.elo_mov_weight,
+            exclude_overtime: self.exclude_overtime,
+            neutral_only: self.neutral_only,
+            ties: self.ties,
+            last_n_games: self.last_n_games,
+            min_opponent_games: self.min_opponent_games,
+            metric: self.metric,
+            forfeit_policy: self.forfeit_policy,
+        }
+    }
+
+    /// Each requested group, or the whole league when there are none.
+    fn groups(&self) -> impl Iterator<Item = Option<&u16>> {
+        let whole_league = self.group.is_empty().then_some(None);
+        self.group.iter().map(Some).chain(whole_league)
+    }
+
+    fn endpoints(&self) -> Endpoints {
+        Endpoints::new(
+            &self.site_api_base,
+            &self.core_api_base,
+            &self.sport,
+            &self.league,
+            self.season_type_code,
+        )
+    }
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct PaginatedItems {
-    //count: Number,
-    page_index: Number,
-    //page_size: Number,
-    page_count: Number,
-    items: Vec<Ref>,
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct Team {
-    id: String,
-    //display_name: String,
-    location: String,
+#[derive(Clone, Copy, Debug)]
+enum SortColumn {
+    Rank,
+    Team,
+    Overall,
+    Defense,
+    Offense,
+    StrengthOfSchedule,
+    Pythagorean,
+    PointsFor,
+    PointsAgainst,
+    Consistency,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct TeamSchedule {
-    team: Team,
-    events: Vec<Event>,
+impl SortColumn {
+    fn value(&self, entry: &TableEntry) -> Option<f64> {
+        match self {
+            SortColumn::Rank => Some(entry.rank as f64),
+            SortColumn::Team => None,
+            SortColumn::Overall => Some(entry.overall_rating),
+            SortColumn::Defense => entry.defense_rating,
+            SortColumn::Offense => entry.offense_rating,
+            SortColumn::StrengthOfSchedule => Some(entry.strength_of_schedule),
+            SortColumn::Pythagorean => Some(entry.pythagorean),
+            SortColumn::PointsFor => entry.points_for,
+            SortColumn::PointsAgainst => entry.points_against,
+            SortColumn::Consistency => entry.consistency,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct CompetitorScore {
-    value: Number,
+#[derive(Clone, Copy, Debug)]
+struct SortKey {
+    column: SortColumn,
+    descending: bool,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct Competitor {
-    id: String,
-    score: Option<CompetitorScore>,
+impl SortKey {
+    /// Teams without a value for the column (e.g. DEF in Elo mode) go last in either direction.
+    fn sort(&self, table: &mut [TableEntry]) {
+        table.sort_by(|e1, e2| {
+            let ordering = match self.column {
+                SortColumn::Team => e1
+                    .team
+                    .to_lowercase()
+                    .cmp(&e2.team.to_lowercase())
+                    .then_with(|| e1.team.cmp(&e2.team)),
+                column => match (column.value(e1), column.value(e2)) {
+                    (Some(v1), Some(v2)) => v1.total_cmp(&v2),
+                    (Some(_), None) => return Ordering::Less,
+                    (None, Some(_)) => return Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                },
+            };
+            match self.descending {
+                true => ordering.reverse(),
+                false => ordering,
+            }
+        });
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Top {
+    Count(usize),
+    Percent(usize),
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct Competition {
-    competitors: Vec<Competitor>,
+impl Top {
+    /// Percentages round up, so any non-empty table keeps at least one team.
+    fn count(&self, len: usize) -> usize {
+        match *self {
+            Top::Count(count) => count,
+            Top::Percent(percent) => (len * percent).div_ceil(100),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct Event {
-    competitions: Vec<Competition>,
+fn parse_top(s: &str) -> Result<Top> {
+    if let Some(percent) = s.strip_suffix('%') {
+        return match percent.parse::<usize>()? {
+            0 => bail!("must be at least 1% (leave out --top to show every team)"),
+            101.. => bail!("can't be more than 100%"),
+            percent => Ok(Top::Percent(percent)),
+        };
+    }
+    match s.parse::<usize>()? {
+        0 => bail!("must be at least 1 (leave out --top to show every team)"),
+        top => Ok(Top::Count(top)),
+    }
 }
 
-struct TeamRating {
-    name: String,
-    defense_rating: f64,
-    offense_rating: f64,
+#[derive(Clone, Copy, Debug)]
+enum Season {
+    Year(u16),
+    Auto,
 }
 
-#[derive(Tabled)]
+fn parse_season(s: &str) -> Result<Season> {
+    match s {
+        "auto" => Ok(Season::Auto),
+        year => Ok(Season::Year(
+            year.parse()
+                .map_err(|_| anyhow!("expected a year or \"auto\""))?,
+        )),
+    }
+}
+
+/// The month each sport's season starts in, and whether ESPN labels its seasons by the year they
+/// end in (2024-25 basketball is season 2025) rather than the year they start in.
+fn season_convention(sport: &str) -> (u32, bool) {
+    match sport {
+        "football" => (8, false),
+        "basketball" | "hockey" => (10, true),
+        "baseball" => (3, false),
+        _ => (1, false),
+    }
+}
+
+/// Before the start month, the latest season is the one that started the previous year, so bowl
+/// games in January still belong to the prior football season.
+fn season_for_date(date: NaiveDate, start_month: u32, labeled_by_end_year: bool) -> u16 {
+    let start_year = match date.month() >= start_month {
+        true => date.year(),
+        false => date.year() - 1,
+    };
+    let season = match labeled_by_end_year {
+        true => start_year + 1,
+        false => start_year,
+    };
+    season as u16
+}
+
+fn parse_sort_key(s: &str) -> Result<SortKey> {
+    let (column, direction) = match s.split_once(':') {
+        Some((column, direction)) => (column, Some(direction)),
+        None => (s, None),
+    };
+    let column = match column.to_ascii_lowercase().as_str() {
+        "#" | "rank" => SortColumn::Rank,
+        "team" => SortColumn::Team,
+        "ovr" => SortColumn::Overall,
+        "def" => SortColumn::Defense,
+        "off" => SortColumn::Offense,
+        "sos" => SortColumn::StrengthOfSchedule,
+        "pyth" => SortColumn::Pythagorean,
+        "pf" => SortColumn::PointsFor,
+        "pa" => SortColumn::PointsAgainst,
+        "cons" => SortColumn::Consistency,
+        _ => bail!(
+            "unknown column \"{}\" (expected #, team, ovr, def, off, sos, pyth, pf, pa, or cons)",
+            column
+        ),
+    };
+    let descending = match direction {
+        Some("asc") => false,
+        Some("desc") => true,
+        Some(direction) => bail!("unknown direction \"{}\" (expected asc or desc)", direction),
+        None => !matches!(column, SortColumn::Rank | SortColumn::Team),
+    };
+    Ok(SortKey { column, descending })
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Rate every team (the default when no command is given)
+    Rate,
+    /// List the IDs and names of the league's teams
+    Teams,
+    /// Rate every team, then compare two of them head to head
+    Compare { first: String, second: String },
+}
+
+fn parse_decay(s: &str) -> Result<f64> {
+    let decay: f64 = s.parse()?;
+    if !(0.0..=1.0).contains(&decay) {
+        bail!("must be between 0 and 1");
+    }
+    Ok(decay)
+}
+
+fn parse_header(s: &str) -> Result<(HeaderName, HeaderValue)> {
+    let Some((name, value)) = s.split_once('=') else {
+        bail!("expected KEY=VALUE");
+    };
+    let name = HeaderName::try_from(name.trim())
+        .with_context(|| format!("invalid header name \"{}\"", name.trim()))?;
+    let value = HeaderValue::try_from(value.trim())
+        .with_context(|| format!("invalid header value \"{}\"", value.trim()))?;
+    Ok((name, value))
+}
+
+fn parse_proxy(s: &str) -> Result<Proxy> {
+    Proxy::all(s).with_context(|| format!("invalid proxy URL \"{}\"", s))
+}
+
+fn table_style(name: &str) -> Result<Theme> {
+    Ok(match name {
+        "psql" => Style::psql().into(),
+        "markdown" => Style::markdown().into(),
+        "ascii" => Style::ascii().into(),
+        "rounded" => Style::rounded().into(),
+        "sharp" => Style::sharp().into(),
+        "blank" => Style::blank().into(),
+        _ => bail!(
+            "unknown table style \"{}\" (expected psql, markdown, ascii, rounded, sharp, or blank)",
+            name
+        ),
+    })
+}
+
+fn parse_team_pair(s: &str) -> Result<(String, String), String> {
+    match s.split_once(',') {
+        Some((first, second)) if !first.is_empty() && !second.is_empty() => {
+            Ok((first.trim().to_string(), second.trim().to_string()))
+        }
+        _ => Err("expected two teams separated by a comma".to_string()),
+    }
+}
+
+/// A half-life of zero would weight every game but the latest at nothing, and a negative one would
+/// favor older games.
+fn parse_halflife(s: &str) -> Result<f64> {
+    let halflife: f64 = s.parse()?;
+    if halflife.is_nan() || halflife <= 0.0 {
+        bail!("must be greater than 0");
+    }
+    Ok(halflife)
+}
+
+#[derive(Clone, Copy, Debug, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+enum Normalization {
+    Range,
+    ZScore,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(&self, to_stdout: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && to_stdout
+                    && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Csv,
+    Tsv,
+    Markdown,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Poll {
+    Ap,
+    Coaches,
+}
+
+impl Poll {
+    /// The poll's `type` in ESPN's rankings response.
+    fn espn_type(&self) -> &'static str {
+        match self {
+            Poll::Ap => "ap",
+            Poll::Coaches => "usa",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Tabled)]
 struct TableEntry {
+    #[tabled(skip)]
+    #[serde(rename = "team_id")]
+    id: String,
+    #[tabled(skip)]
+    #[serde(skip)]
+    conference_id: Option<String>,
+    #[tabled(skip)]
+    #[serde(skip)]
+    precision: usize,
     #[tabled(rename = "#")]
     rank: usize,
+    #[tabled(rename = "Δ")]
+    #[tabled(display_with = "format_optional_text")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    movement: Option<String>,
     #[tabled(rename = "Team")]
     team: String,
+    /// Only for the table and delimited output; JSON always has `id` as "team_id".
+    #[tabled(rename = "Team ID")]
+    #[tabled(display_with = "format_optional_text")]
+    #[serde(skip)]
+    team_id: Option<String>,
+    #[tabled(rename = "W-L")]
+    record: String,
+    #[tabled(rename = "QW")]
+    #[tabled(display_with = "format_optional_count")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality_wins: Option<usize>,
     #[tabled(rename = "OVR")]
-    #[tabled(display_with = "float2")]
+    #[tabled(display_with("format_rating", self.overall_rating, self.precision))]
     overall_rating: f64,
     #[tabled(rename = "DEF")]
-    #[tabled(display_with = "float2")]
-    defense_rating: f64,
+    #[tabled(display_with("format_optional_rating", self.defense_rating, self.precision))]
+    defense_rating: Option<f64>,
     #[tabled(rename = "OFF")]
+    #[tabled(display_with("format_optional_rating", self.offense_rating, self.precision))]
+    offense_rating: Option<f64>,
+    #[tabled(rename = "SOS")]
+    #[tabled(display_with("format_rating", self.strength_of_schedule, self.precision))]
+    strength_of_schedule: f64,
+    #[tabled(rename = "PYTH")]
     #[tabled(display_with = "float2")]
-    offense_rating: f64,
+    pythagorean: f64,
+    #[tabled(rename = "PF")]
+    #[tabled(display_with("format_optional_rating", self.points_for, 1))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    points_for: Option<f64>,
+    #[tabled(rename = "PA")]
+    #[tabled(display_with("format_optional_rating", self.points_against, 1))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    points_against: Option<f64>,
+    #[tabled(rename = "CONS")]
+    #[tabled(display_with("format_optional_rating", self.consistency, self.precision))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    consistency: Option<f64>,
 }
 
-fn float2(n: &f64) -> String {
-    format!("{:.2}", n)
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    metadata: Metadata<'a>,
+    teams: &'a [TableEntry],
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+/// The parameters a JSON result was generated with, so archived output is self-describing. Every
+/// rating option is flattened in from `RatingOptions`, so new options are recorded without having
+/// to be listed here too.
+#[derive(Serialize)]
+struct Metadata<'a> {
+    sport: &'a str,
+    league: &'a str,
+    season: u16,
+    group: &'a [u16],
+    adjust_depth: Option<u32>,
+    #[serde(flatten)]
+    options: &'a RatingOptions,
+    defense_weight: f64,
+    offense_weight: f64,
+    min_games: usize,
+    normalize: Option<Normalization>,
+    generated_at: String,
+}
 
-    let client = Client::new();
+#[derive(Tabled)]
+struct ConferenceEntry {
+    #[tabled(rename = "#")]
+    rank: usize,
+    #[tabled(rename = "Conference")]
+    conference: String,
+    #[tabled(rename = "Teams")]
+    teams: usize,
+    #[tabled(rename = "AVG")]
+    #[tabled(display_with("format_rating", self.average, self.precision))]
+    average: f64,
+    #[tabled(rename = "MED")]
+    #[tabled(display_with("format_rating", self.median, self.precision))]
+    median: f64,
+    #[tabled(skip)]
+    precision: usize,
+}
 
-    let team_ids = get_team_ids(
-        &client,
-        args.sport.as_str(),
-        args.league.as_str(),
-        &args.season,
-        args.group.as_ref(),
-    )
-    .await?;
+#[derive(Tabled)]
+struct PollEntry {
+    #[tabled(rename = "Poll", display_with = "format_optional_rank")]
+    poll_rank: Option<usize>,
+    #[tabled(rename = "Team")]
+    team: String,
+    #[tabled(rename = "Model", display_with = "format_optional_rank")]
+    model_rank: Option<usize>,
+}
 
-    let urls: Vec<String> = team_ids
-        .par_iter()
-        .progress()
-        .with_style(ProgressStyle::with_template(
-            "{msg} {wide_bar} {pos}/{len}",
-        )?)
-        .with_message("Generating URLs")
-        .map(|team_id| {
-            format!(
-                "https://site.api.espn.com/apis/site/v2/sports/{}/{}/teams/{}/schedule?season={}",
-                args.sport, args.league, team_id, args.season
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Builds the one `Client` shared (by cloning) across every request, keeping enough idle
+/// connections around to serve `max_concurrency` requests at once.
+fn build_client(args: &Args) -> Result<Client> {
+    let headers: HeaderMap = args.headers.iter().cloned().collect();
+    let mut builder = Client::builder()
+        .timeout(Duration::from_secs(args.timeout))
+        .user_agent(&args.user_agent)
+        .default_headers(headers)
+        .pool_max_idle_per_host(args.max_concurrency)
+        .gzip(!args.no_compression)
+        .deflate(!args.no_compression);
+    // Without an explicit proxy, reqwest picks one up from the environment.
+    if let Some(proxy) = &args.proxy {
+        builder = builder.proxy(proxy.clone());
+    }
+    Ok(builder.build()?)
+}
+
+/// One row per game in the team's schedule, saying whether it counted toward the rating and if not
+/// why. Skip reasons don't depend on earlier passes, so no ratings need to be computed first.
+fn audit(
+    team_schedule: &TeamSchedule,
+    team_schedules: &[TeamSchedule],
+    fbs_team_ids: &[&str],
+    options: &RatingOptions,
+    style: &Theme,
+) -> String {
+    let index = ScheduleIndex::new(team_schedules, fbs_team_ids, options);
+    let game_sums: HashMap<&str, TeamGameSums> = team_schedules
+        .iter()
+        .map(|team_schedule| {
+            (
+                team_schedule.team.id.as_str(),
+                team_game_sums(team_schedule, &index, &HashMap::new(), options),
             )
         })
         .collect();
+    let recent = rated_events(team_schedule, &index, options);
 
-    let pb = ProgressBar::new(urls.len() as u64);
-
-    let team_schedules: Vec<TeamSchedule> = pb
-        .wrap_stream(stream::iter(urls))
-        .with_style(ProgressStyle::with_template(
-            "{msg} {wide_bar} {pos}/{len}",
-        )?)
-        .with_message("Fetching scores")
-        .map(|url| {
-            let client = client.clone();
-            tokio::spawn(async move {
-                let resp = client.get(url).send().await?;
-                resp.json::<TeamSchedule>().await
-            })
-        })
-        .buffer_unordered(args.max_concurrency)
-        .filter_map(|x| async {
-            match x {
-                Ok(Ok(x)) => Some(x),
-                _ => None,
+    let mut builder = Builder::default();
+    builder.push_record(["Date", "Opponent", "Score", "Status"]);
+    for event in &team_schedule.events {
+        let competition = event.competitions.last();
+        let competitors = competition
+            .map(|competition| competition.competitors.as_slice())
+            .unwrap_or_default();
+        let opponent = competitors
+            .iter()
+            .find(|competitor| competitor.id != team_schedule.team.id);
+        let team = competitors
+            .iter()
+            .find(|competitor| competitor.id == team_schedule.team.id);
+        let score = |competitor: Option<&Competitor>| match competition
+            .zip(competitor)
+            .and_then(|(competition, competitor)| options.points(competition, competitor))
+        {
+            Some(score) => score.to_string(),
+            None => "?".to_string(),
+        };
+        let status = match rated_game(team_schedule, event, &index, &game_sums, options) {
+            Ok(_) if !recent.iter().any(|recent| std::ptr::eq(*recent, event)) => {
+                SkipReason::NotRecent(options.last_n_games.unwrap_or_default()).to_string()
             }
-        })
-        .collect()
-        .await;
+            Ok(_) => "counted".to_string(),
+            Err(reason) => reason.to_string(),
+        };
+        builder.push_record([
+            event
+                .date
+                .map(|date| date.format("%Y-%m-%d").to_string())
+                .unwrap_or_default(),
+            match opponent {
+                Some(opponent) => match index.schedules_by_id.get(opponent.id.as_str()) {
+                    Some(schedule) => schedule.team.name(options.name_field).to_string(),
+                    None => format!("team {}", opponent.id),
+                },
+                None => "-".to_string(),
+            },
+            format!("{}-{}", score(team), score(opponent)),
+            status,
+        ]);
+    }
+    let mut rendered = builder.build();
+    rendered.with(style.clone());
+    rendered.to_string()
+}
 
-    let fbs_team_ids: Vec<&str> = team_schedules
-        .par_iter()
-        .progress()
-        .with_style(ProgressStyle::with_template(
-            "{msg} {wide_bar} {pos}/{len}",
-        )?)
-        .with_message("Extracting FBS team IDs")
-        .map(|team_schedule| team_schedule.team.id.as_str())
-        .collect();
+/// Finds a ranked team by ESPN id or by (case-insensitive) name.
+fn find_entry(table: &[TableEntry], query: &str) -> Result<usize> {
+    table
+        .iter()
+        .position(|entry| entry.id == query || entry.team.eq_ignore_ascii_case(query))
+        .ok_or_else(|| anyhow!("no ranked team matches \"{}\"", query))
+}
 
-    let team_ratings: Vec<TeamRating> = team_schedules
-        .par_iter()
-        .progress()
-        .with_style(ProgressStyle::with_template(
-            "{msg} {wide_bar} {pos}/{len}",
-        )?)
-        .with_message("Calculating ratings")
-        .filter(|team_schedule| team_schedule.events.len() > 0)
-        .map(|team_schedule| {
-            let mut defense_rating: f64 = 0.0;
-            let mut offense_rating: f64 = 0.0;
-            let mut count: u8 = 0;
-            'events_loop: for event in &team_schedule.events {
-                let Some(competition) = event.competitions.last() else {
-                    continue 'events_loop;
-                };
-                let c_index = {
-                    if competition.competitors[0].id == team_schedule.team.id {
-                        0 as usize
-                    } else {
-                        1 as usize
-                    }
-                };
-                let competitor = &competition.competitors[c_index];
-                let opponent = &competition.competitors[c_index ^ 1];
-                if !fbs_team_ids.contains(&opponent.id.as_str()) {
-                    continue 'events_loop;
-                }
-                let Some(competitor_score) = &competitor.score else {
-                    continue 'events_loop;
-                };
-                let Some(opponent_score) = &opponent.score else {
-                    continue 'events_loop;
-                };
-                let Some(competitor_score_f64) = competitor_score.value.as_f64() else {
-                    continue 'events_loop;
-                };
-                let Some(opponent_score_f64) = opponent_score.value.as_f64() else {
-                    continue 'events_loop;
-                };
-                let Some(opponent_team_schedule) = ({
-                    let mut opponent_ts: Option<&TeamSchedule> = None;
-                    for ts in &team_schedules {
-                        if ts.team.id == opponent.id {
-                            opponent_ts = Some(ts);
-                        }
-                    }
-                    opponent_ts
-                }) else {
-                    continue 'events_loop;
-                };
-                let mut opponent_avg_scored: f64 = 0.0;
-                let mut opponent_avg_allowed: f64 = 0.0;
-                let mut o_count: u8 = 0;
-                'o_events_loop: for o_event in &opponent_team_schedule.events {
-                    let Some(o_competition) = o_event.competitions.last() else {
-                        continue 'o_events_loop;
-                    };
-                    let o_c_index = {
-                        if o_competition.competitors[0].id == opponent.id {
-                            0 as usize
-                        } else {
-                            1 as usize
-                        }
-                    };
-                    let o_competitor = &o_competition.competitors[o_c_index];
-                    let o_opponent = &o_competition.competitors[o_c_index ^ 1];
-                    if o_opponent.id == team_schedule.team.id {
-                        continue 'o_events_loop;
-                    }
-                    if !fbs_team_ids.contains(&o_opponent.id.as_str()) {
-                        continue 'o_events_loop;
-                    }
-                    let Some(o_competitor_score) = &o_competitor.score else {
-                        continue 'o_events_loop;
-                    };
-                    let Some(o_opponent_score) = &o_opponent.score else {
-                        continue 'o_events_loop;
-                    };
-                    let Some(o_competitor_score_f64) = o_competitor_score.value.as_f64() else {
-                        continue 'o_events_loop;
-                    };
-                    let Some(o_opponent_score_f64) = o_opponent_score.value.as_f64() else {
-                        continue 'o_events_loop;
-                    };
-                    opponent_avg_allowed += o_opponent_score_f64;
-                    opponent_avg_scored += o_competitor_score_f64;
-                    o_count += 1;
-                }
+fn compare_summary(
+    first: &TableEntry,
+    second: &TableEntry,
+    team_schedules: &[TeamSchedule],
+) -> String {
+    let (better, worse) = match first.overall_rating >= second.overall_rating {
+        true => (first, second),
+        false => (second, first),
+    };
+    let mut lines = vec![format!(
+        "{} is rated {} points better than {}",
+        better.team,
+        format_rating(
+            better.overall_rating - worse.overall_rating,
+            better.precision
+        ),
+        worse.team
+    )];
+
+    let head_to_head = team_schedules
+        .iter()
+        .filter(|team_schedule| team_schedule.team.id == first.id)
+        .flat_map(|team_schedule| &team_schedule.events)
+        .filter_map(|event| event.competitions.last().map(|c| (event, c)));
+    for (event, competition) in head_to_head {
+        let score = |id: &str| {
+            competition
+                .competitors
+                .iter()
+                .find(|competitor| competitor.id == id)
+                .and_then(|competitor| competitor.score.as_ref())
+                .and_then(|score| score.value.as_f64())
+        };
+        let (Some(first_score), Some(second_score)) = (score(&first.id), score(&second.id)) else {
+            continue;
+        };
+        let date = match event.date {
+            Some(date) => format!(" on {}", date.format("%Y-%m-%d")),
+            None => String::new(),
+        };
+        let site = match competition.neutral_site {
+            true => " (neutral site)",
+            false => "",
+        };
+        lines.push(format!(
+            "Played{}: {} {}, {} {}{}",
+            date, first.team, first_score, second.team, second_score, site
+        ));
+    }
 
-                opponent_avg_allowed /= o_count as f64;
-                opponent_avg_scored /= o_count as f64;
+    lines.join("\n")
+}
 
-                defense_rating += opponent_avg_scored - opponent_score_f64;
-                offense_rating += competitor_score_f64 - opponent_avg_allowed;
-                count += 1;
-            }
+/// Lists each counted game's share of the team's DEF and OFF, with a total row matching the ratings.
+fn team_breakdown(rating: &TeamRating, precision: usize, style: &Theme) -> String {
+    let mut builder = Builder::default();
+    builder.push_record(["Date", "Opponent", "Result", "Weight", "DEF", "OFF"]);
+    for game in &rating.breakdown {
+        let result = match game.points_scored.total_cmp(&game.points_allowed) {
+            Ordering::Greater => "W",
+            Ordering::Less => "L",
+            Ordering::Equal => "T",
+        };
+        builder.push_record([
+            game.date
+                .map(|date| date.format("%Y-%m-%d").to_string())
+                .unwrap_or_default(),
+            game.opponent.clone(),
+            format!("{} {}-{}", result, game.points_scored, game.points_allowed),
+            format!("{:.3}", game.weight),
+            format_rating(game.defense, precision),
+            format_rating(game.offense, precision),
+        ]);
+    }
+    builder.push_record([
+        "Total".to_string(),
+        String::new(),
+        String::new(),
+        String::new(),
+        format_rating(rating.defense_rating, precision),
+        format_rating(rating.offense_rating, precision),
+    ]);
+    let mut rendered = builder.build();
+    rendered
+        .with(style.clone())
+        .modify(Columns::new(3..), Alignment::right());
+    rendered.to_string()
+}
 
-            defense_rating /= count as f64;
-            offense_rating /= count as f64;
+/// Each listed team's games and rating totals, from which DEF, OFF, and OVR can be recomputed.
+fn explanation(
+    table: &[TableEntry],
+    team_ratings: &[TeamRating],
+    defense_weight: f64,
+    offense_weight: f64,
+    precision: usize,
+    style: &Theme,
+) -> String {
+    let ratings: HashMap<&str, &TeamRating> = team_ratings
+        .iter()
+        .map(|rating| (rating.id.as_str(), rating))
+        .collect();
+    let mut builder = Builder::default();
+    builder.push_record([
+        "#", "Team", "Games", "Weight", "DEF sum", "OFF sum", "DEF", "OFF", "OVR",
+    ]);
+    for entry in table {
+        let Some(rating) = ratings.get(entry.id.as_str()) else {
+            continue;
+        };
+        builder.push_record([
+            entry.rank.to_string(),
+            entry.team.clone(),
+            rating.games.to_string(),
+            format!("{:.3}", rating.total_weight),
+            format_rating(rating.defense_total, precision),
+            format_rating(rating.offense_total, precision),
+            format_rating(rating.defense_rating, precision),
+            format_rating(rating.offense_rating, precision),
+            format_rating(entry.overall_rating, precision),
+        ]);
+    }
+    let mut rendered = builder.build();
+    rendered
+        .with(style.clone())
+        .modify(Columns::single(0), Alignment::right())
+        .modify(Columns::new(2..), Alignment::right());
+    let overall = match defense_weight == 1.0 && offense_weight == 1.0 {
+        true => "OVR = DEF + OFF".to_string(),
+        false => format!("OVR = {} * DEF + {} * OFF", defense_weight, offense_weight),
+    };
+    format!(
+        "DEF = DEF sum / Weight, OFF = OFF sum / Weight, {}\n{}",
+        overall, rendered
+    )
+}
 
-            return TeamRating {
-                name: team_schedule.team.location.clone(),
-                defense_rating,
-                offense_rating,
-            };
+/// The header followed by one record per entry, leaving out optional columns that no entry has a
+/// value for.
+fn table_records(table: &[TableEntry]) -> Vec<Vec<String>> {
+    let headers = TableEntry::headers();
+    let visible: Vec<bool> = headers
+        .iter()
+        .map(|header| match header.as_ref() {
+            "PF" | "PA" => table.iter().any(|entry| entry.points_for.is_some()),
+            "CONS" => table.iter().any(|entry| entry.consistency.is_some()),
+            "Δ" => table.iter().any(|entry| entry.movement.is_some()),
+            "Team ID" => table.iter().any(|entry| entry.team_id.is_some()),
+            "QW" => table.iter().any(|entry| entry.quality_wins.is_some()),
+            _ => true,
         })
         .collect();
-
-    let mut table: Vec<TableEntry> = vec![];
-
-    for rating in &team_ratings {
-        table.push(TableEntry {
-            rank: 0,
-            team: rating.name.clone(),
-            overall_rating: rating.defense_rating + rating.offense_rating,
-            defense_rating: rating.defense_rating,
-            offense_rating: rating.offense_rating,
+    std::iter::once(headers)
+        .chain(table.iter().map(|entry| entry.fields()))
+        .map(|record| {
+            record
+                .into_iter()
+                .zip(&visible)
+                .filter(|(_, visible)| **visible)
+                .map(|(field, _)| field.into_owned())
+                .collect()
         })
-    }
+        .collect()
+}
 
-    table.sort_by(|e1, e2| e1.overall_rating.total_cmp(&e2.overall_rating));
+/// Team names are never cut shorter than this, even if the table then overflows `--width`.
+const MIN_TEAM_WIDTH: usize = 4;
 
-    table.reverse();
+/// The width the table is fit to, if any. Output that isn't going to a terminal isn't limited
+/// unless asked.
+fn table_width(args: &Args) -> Option<usize> {
+    let width = args.width.or_else(|| match args.output {
+        Some(_) => None,
+        None => Term::stdout().size_checked().map(|(_, columns)| columns),
+    });
+    width.map(usize::from)
+}
 
-    for i in 0..table.len() {
-        table[i].rank = i + 1;
+/// Renders the table with numeric columns right-aligned, coloring ratings above `color_center`
+/// green and below it red.
+fn render_table(
+    table: &[TableEntry],
+    style: &Theme,
+    color_center: Option<f64>,
+    width: Option<usize>,
+) -> String {
+    let records = table_records(table);
+    let headers = records[0].clone();
+    let team_column = headers.iter().position(|header| header == "Team");
+    let team_width = team_column
+        .and_then(|col| {
+            records
+                .iter()
+                .map(|record| record[col].chars().count())
+                .max()
+        })
+        .unwrap_or_default();
+    let mut rendered = Builder::from_iter(records).build();
+    rendered.with(style.clone());
+    for (col, header) in headers.iter().enumerate() {
+        if header != "Team" {
+            rendered.modify(Columns::single(col), Alignment::right());
+        }
     }
 
-    if args.defense {
-        table.sort_by(|e1, e2| e1.defense_rating.total_cmp(&e2.defense_rating));
-        table.reverse();
-    } else if args.offense {
-        table.sort_by(|e1, e2| e1.offense_rating.total_cmp(&e2.offense_rating));
-        table.reverse();
+    // Only team names give way, since a cut-off rating would be misleading. A table whose other
+    // columns alone are too wide for `width` keeps a few characters of each name and overflows.
+    if let (Some(width), Some(team_column)) = (width, team_column) {
+        let overflow = rendered.total_width().saturating_sub(width);
+        if overflow > 0 {
+            let team_width = team_width.saturating_sub(overflow).max(MIN_TEAM_WIDTH);
+            rendered.modify(
+                Columns::single(team_column),
+                Width::truncate(team_width).suffix("…"),
+            );
+        }
     }
 
-    if args.reverse {
-        table.reverse();
-    }
+    if let Some(center) = color_center {
+        let column = |name: &str| headers.iter().position(|header| header == name);
+        for (row, entry) in table.iter().enumerate() {
+            let ratings = [
+                ("OVR", Some(entry.overall_rating)),
+                ("DEF", entry.defense_rating),
+                ("OFF", entry.offense_rating),
+            ];
+            for (name, rating) in ratings {
+                let (Some(col), Some(rating)) = (column(name), rating) else {
+                    continue;
+                };
+                let color = match rating.total_cmp(&center) {
+                    Ordering::Greater => Color::FG_GREEN,
+                    Ordering::Less => Color::FG_RED,
+                    Ordering::Equal => continue,
+                };
+                rendered.modify((row + 1, col), color);
+            }
+        }
+    }
+
+    rendered.to_string()
+}
 
-    if args.top.is_some() {
-        table.truncate(args.top.unwrap())
+/// Lists the poll's teams alongside their model rank, followed by the model's teams of the same
+/// depth that the poll left out.
+fn poll_comparison(
+    table: &[TableEntry],
+    ranking: &Ranking,
+    style: &Theme,
+    name_field: NameField,
+) -> String {
+    let mut entries: Vec<PollEntry> = vec![];
+    let mut rank_pairs: Vec<(f64, f64)> = vec![];
+
+    for rank in &ranking.ranks {
+        let model_entry = table.iter().find(|entry| entry.id == rank.team.id);
+        if let Some(model_entry) = model_entry {
+            rank_pairs.push((rank.current as f64, model_entry.rank as f64));
+        }
+        entries.push(PollEntry {
+            poll_rank: Some(rank.current),
+            team: match model_entry {
+                Some(model_entry) => model_entry.team.clone(),
+                None => rank.team.name(name_field).to_string(),
+            },
+            model_rank: model_entry.map(|entry| entry.rank),
+        });
     }
 
-    let style = Style::psql();
+    for entry in table.iter().take(ranking.ranks.len()) {
+        if ranking.ranks.iter().all(|rank| rank.team.id != entry.id) {
+            entries.push(PollEntry {
+                poll_rank: None,
+                team: entry.team.clone(),
+                model_rank: Some(entry.rank),
+            });
+        }
+    }
 
-    println!("{}", Table::new(table).with(style));
+    let correlation = match spearman_correlation(&rank_pairs) {
+        Some(correlation) => format!(
+            "{}: Spearman rank correlation {:.3} over {} teams ranked by both",
+            ranking.name,
+            correlation,
+            rank_pairs.len()
+        ),
+        None => format!(
+            "{}: too few teams ranked by both to compute a rank correlation",
+            ranking.name
+        ),
+    };
 
-    Ok(())
+    let mut rendered = Table::new(entries);
+    rendered.with(style.clone());
+    format!("{}\n{}", correlation, rendered)
 }
 
-async fn get_team_ids(
-    client: &Client,
-    sport: &str,
-    league: &str,
-    season: &u16,
-    group: Option<&u16>,
-) -> Result<Vec<u32>> {
-    let mut team_ids: Vec<u32> = vec![];
+/// Spearman's rank correlation, computed as the Pearson correlation of the re-ranked values so
+/// that tied ranks are averaged.
+fn spearman_correlation(pairs: &[(f64, f64)]) -> Option<f64> {
+    if pairs.len() < 2 {
+        return None;
+    }
+    let first = average_ranks(&pairs.iter().map(|pair| pair.0).collect::<Vec<f64>>());
+    let second = average_ranks(&pairs.iter().map(|pair| pair.1).collect::<Vec<f64>>());
 
-    let mut page_index = 0;
+    let n = pairs.len() as f64;
+    let first_mean = first.iter().sum::<f64>() / n;
+    let second_mean = second.iter().sum::<f64>() / n;
+    let mut covariance = 0.0;
+    let mut first_variance = 0.0;
+    let mut second_variance = 0.0;
+    for (a, b) in first.iter().zip(&second) {
+        covariance += (a - first_mean) * (b - second_mean);
+        first_variance += (a - first_mean).powi(2);
+        second_variance += (b - second_mean).powi(2);
+    }
 
-    loop {
-        page_index += 1;
-        let url = match group.is_some() {
-            true => format!("https://sports.core.api.espn.com/v2/sports/{}/leagues/{}/seasons/{}/types/2/groups/{}/teams?limit=1000&page={}", sport, league, season, group.unwrap(), page_index),
-            false => format!("https://sports.core.api.espn.com/v2/sports/{}/leagues/{}/seasons/{}/teams?limit=1000&page={}", sport, league, season, page_index),
-        };
+    if first_variance == 0.0 || second_variance == 0.0 {
+        return None;
+    }
+    Some(covariance / (first_variance * second_variance).sqrt())
+}
 
-        let teams_response = client.get(url).send().await?;
-        let teams_response_data = teams_response.json::<PaginatedItems>().await?;
-
-        let mut iteration_team_ids: Vec<u32> = teams_response_data
-            .items
-            .par_iter()
-            .progress()
-            .with_style(ProgressStyle::with_template(
-                "{msg} {wide_bar} {pos}/{len}",
-            )?)
-            .with_message("Extracting team IDs")
-            .filter_map(|item| {
-                let Some(first_split) = item.url.rsplit_once('/') else {
-                    return None;
-                };
-                let Some(second_split) = first_split.1.split_once('?') else {
-                    return None;
+fn average_ranks(values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && values[order[end]] == values[order[start]] {
+            end += 1;
+        }
+        let average = (start + end + 1) as f64 / 2.0;
+        for &i in &order[start..end] {
+            ranks[i] = average;
+        }
+        start = end;
+    }
+    ranks
+}
+
+/// Ranks conferences by the average overall rating of their teams, with teams that have no
+/// conference counted together as "Other".
+fn conference_ratings(
+    table: &[TableEntry],
+    conference_names: &HashMap<String, String>,
+    style: &Theme,
+    precision: usize,
+) -> String {
+    let mut conferences: Vec<(String, Vec<f64>)> = vec![];
+    for entry in table {
+        let name = entry
+            .conference_id
+            .as_ref()
+            .and_then(|id| conference_names.get(id))
+            .cloned()
+            .unwrap_or_else(|| "Other".to_string());
+        match conferences.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, ratings)) => ratings.push(entry.overall_rating),
+            None => conferences.push((name, vec![entry.overall_rating])),
+        }
+    }
+
+    let mut entries: Vec<ConferenceEntry> = conferences
+        .into_iter()
+        .map(|(conference, mut ratings)| ConferenceEntry {
+            rank: 0,
+            conference,
+            teams: ratings.len(),
+            average: ratings.iter().sum::<f64>() / ratings.len() as f64,
+            median: median(&mut ratings),
+            precision,
+        })
+        .collect();
+    entries.sort_by(|c1, c2| {
+        c2.average
+            .total_cmp(&c1.average)
+            .then_with(|| c1.conference.cmp(&c2.conference))
+    });
+    for (i, entry) in entries.iter_mut().enumerate() {
+        entry.rank = i + 1;
+    }
+
+    let mut rendered = Table::new(entries);
+    rendered
+        .with(style.clone())
+        .modify(Columns::new(2..), Alignment::right());
+    rendered.to_string()
+}
+
+/// The middle value, or the mean of the two middle values; `values` must not be empty.
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    match values.len() % 2 {
+        0 => (values[mid - 1] + values[mid]) / 2.0,
+        _ => values[mid],
+    }
+}
+
+/// Renders one table per conference, ordered by the conference's average overall rating, with
+/// each team re-ranked within its conference.
+fn group_by_conference(
+    table: Vec<TableEntry>,
+    conference_names: &HashMap<String, String>,
+    style: &Theme,
+    color_center: Option<f64>,
+    width: Option<usize>,
+) -> String {
+    let mut conferences: Vec<(String, Vec<TableEntry>)> = vec![];
+    for entry in table {
+        let name = entry
+            .conference_id
+            .as_ref()
+            .and_then(|id| conference_names.get(id))
+            .cloned()
+            .unwrap_or_else(|| "Other".to_string());
+        match conferences.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, entries)) => entries.push(entry),
+            None => conferences.push((name, vec![entry])),
+        }
+    }
+
+    let mut conferences: Vec<(String, f64, Vec<TableEntry>)> = conferences
+        .into_iter()
+        .map(|(name, mut entries)| {
+            let mut by_rating: Vec<usize> = (0..entries.len()).collect();
+            by_rating.sort_by(|&i, &j| {
+                entries[j]
+                    .overall_rating
+                    .total_cmp(&entries[i].overall_rating)
+            });
+            for (rank, i) in by_rating.into_iter().enumerate() {
+                entries[i].rank = rank + 1;
+            }
+            let average =
+                entries.iter().map(|e| e.overall_rating).sum::<f64>() / entries.len() as f64;
+            (name, average, entries)
+        })
+        .collect();
+    conferences.sort_by(|c1, c2| c2.1.total_cmp(&c1.1));
+
+    conferences
+        .into_iter()
+        .map(|(name, average, entries)| {
+            format!(
+                "{} (average OVR {})\n{}",
+                name,
+                format_rating(average, entries[0].precision),
+                render_table(&entries, style, color_center, width)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+fn methodology(args: &Args) -> String {
+    let endpoints = args.endpoints();
+    let mut lines = vec![
+        "Data source: ESPN".to_string(),
+        format!(
+            "  Teams:     {}",
+            args.groups()
+                .map(|group| endpoints.teams(&args.season, group))
+                .collect::<Vec<String>>()
+                .join("\n             ")
+        ),
+        format!(
+            "  Schedules: {}",
+            endpoints.schedule("{team_id}", &args.season)
+        ),
+        String::new(),
+    ];
+
+    if args.elo {
+        lines.push(format!(
+            "Rating: OVR is an Elo rating starting at {} and updated after every game in date order with K = {} * (1 + {} * ln(1 + margin))",
+            ELO_INITIAL_RATING, args.elo_k_factor, args.elo_mov_weight
+        ));
+    } else {
+        lines.push("Rating: averages over each team's counted games, where".to_string());
+        match args.model() {
+            Model::RatingDiff if args.adjust_depth == Some(0) => {
+                lines.push("  DEF = league average points - points allowed".to_string());
+                lines.push("  OFF = points scored - league average points".to_string());
+            }
+            Model::OpponentAverage => {
+                lines.push(
+                    "  DEF = opponent's average points scored in its other games - points allowed"
+                        .to_string(),
+                );
+                lines.push(
+                    "  OFF = points scored - opponent's average points allowed in its other games"
+                        .to_string(),
+                );
+            }
+            Model::RatingDiff => {
+                lines.push(
+                    "  DEF = league average points - points allowed + opponent's OFF".to_string(),
+                );
+                lines.push(
+                    "  OFF = points scored - league average points + opponent's DEF".to_string(),
+                );
+            }
+        }
+        lines.push(
+            match args.defense_weight == 1.0 && args.offense_weight == 1.0 {
+                true => "  OVR = DEF + OFF".to_string(),
+                false => format!(
+                    "  OVR = {} * DEF + {} * OFF",
+                    args.defense_weight, args.offense_weight
+                ),
+            },
+        );
+        match args.model() {
+            Model::RatingDiff if args.adjust_depth == Some(0) => {}
+            Model::OpponentAverage if args.adjustment_decay() < 1.0 && args.iterations() > 1 => {
+                lines.push(format!(
+                    "  Opponent averages are corrected for their own schedules {} levels deep, each level counting {} times the one above it",
+                    args.iterations(),
+                    args.adjustment_decay()
+                ))
+            }
+            Model::OpponentAverage if args.iterations() > 1 => lines.push(format!(
+                "  Opponent averages are corrected for their own schedules over up to {} passes",
+                args.iterations()
+            )),
+            Model::OpponentAverage => {}
+            Model::RatingDiff => lines.push(format!(
+                "  Opponents' ratings come from the previous pass, repeated until they converge or for up to {} passes",
+                args.iterations()
+            )),
+        }
+    }
+
+    lines.push(String::new());
+    lines.push("Adjustments:".to_string());
+    lines.extend(
+        active_adjustments(args)
+            .into_iter()
+            .map(|adjustment| format!("  {}", adjustment)),
+    );
+
+    lines.join("\n")
+}
+
+/// The line under a Markdown table saying what the ratings cover, for pasting alongside it.
+fn markdown_footer(args: &Args) -> String {
+    format!(
+        "_Ratings for {}/{}, {} season: {}._",
+        args.sport,
+        args.league,
+        args.season,
+        active_adjustments(args).join("; ")
+    )
+}
+
+fn active_adjustments(args: &Args) -> Vec<String> {
+    let mut adjustments = vec![match args.week {
+        Some(week) => format!("regular season games through week {} only", week),
+        None => format!(
+            "{} games only",
+            match args.season_type {
+                SeasonTypeFilter::Regular => "regular season",
+                SeasonTypeFilter::Postseason => "postseason",
+                SeasonTypeFilter::All => "regular season and postseason",
+            }
+        ),
+    }];
+    if !args.include_all_opponents {
+        adjustments.push("games against teams outside the fetched teams are ignored".to_string());
+    }
+    if args.exclude_overtime {
+        adjustments.push("overtime games are ignored".to_string());
+    }
+    if args.neutral_only {
+        adjustments.push("only neutral-site games are counted".to_string());
+    }
+    match args.ties {
+        TiePolicy::Count => {}
+        TiePolicy::Half => adjustments.push("tied games count at half weight".to_string()),
+        TiePolicy::Exclude => adjustments.push("tied games are ignored".to_string()),
+    }
+    if let ForfeitPolicy::CountAsLoss = args.forfeit_policy {
+        adjustments.push("forfeits count as 1-0 losses for the team that forfeited".to_string());
+    }
+    if args.home_advantage != 0.0 {
+        adjustments.push(format!(
+            "home-field advantage of {} points",
+            args.home_advantage
+        ));
+        if args.neutralize_opponents {
+            adjustments.push("opponent averages neutralized for location".to_string());
+        }
+    }
+    match args.mov_transform() {
+        MovTransform::None => {}
+        MovTransform::Sqrt => adjustments.push("margins dampened by a square root".to_string()),
+        MovTransform::Log => adjustments.push("margins dampened by ln(1 + margin)".to_string()),
+        MovTransform::Cap => adjustments.push(format!(
+            "margins capped at {} points",
+            args.mov_cap.unwrap_or_default()
+        )),
+    }
+    if let Some(recency_halflife) = args.recency_halflife {
+        adjustments.push(format!(
+            "game weights halve every {} days",
+            recency_halflife
+        ));
+    }
+    if let Some(last_n_games) = args.last_n_games {
+        adjustments.push(format!(
+            "only each team's {} most recent games are counted",
+            last_n_games
+        ));
+    }
+    if args.min_opponent_games > 1 {
+        adjustments.push(format!(
+            "games against opponents with fewer than {} other counted games are skipped",
+            args.min_opponent_games
+        ));
+    }
+    if args.min_games > 1 {
+        adjustments.push(format!(
+            "teams with fewer than {} counted games are not listed",
+            args.min_games
+        ));
+    }
+    adjustments
+}
+
+/// Rescales each rating column across every rated team, so it runs before `--top` trims the table.
+fn normalize(table: &mut [TableEntry], normalization: Normalization) {
+    let columns: [fn(&mut TableEntry) -> Option<&mut f64>; 3] = [
+        |entry| Some(&mut entry.overall_rating),
+        |entry| entry.defense_rating.as_mut(),
+        |entry| entry.offense_rating.as_mut(),
+    ];
+    for column in columns {
+        let values: Vec<f64> = table
+            .iter_mut()
+            .filter_map(|e| column(e).copied())
+            .collect();
+        if values.is_empty() {
+            continue;
+        }
+        let n = values.len() as f64;
+        let (offset, scale) = match normalization {
+            Normalization::Range => {
+                let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+                let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                (min, (max - min) / 100.0)
+            }
+            Normalization::ZScore => {
+                let mean = values.iter().sum::<f64>() / n;
+                let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+                (mean, variance.sqrt())
+            }
+        };
+        for entry in table.iter_mut() {
+            if let Some(value) = column(entry) {
+                *value = match scale > 0.0 {
+                    true => (*value - offset) / scale,
+                    false => 0.0,
                 };
-                let Ok(team_id) = second_split.0.parse::<u32>() else {
-                    return None;
+            }
+        }
+    }
+}
+
+fn float2(n: &f64) -> String {
+    format!("{:.2}", n)
+}
+
+/// Mean and population standard deviation of the listed teams' overall ratings.
+fn summary_stats(table: &[TableEntry], precision: usize) -> String {
+    let n = table.len() as f64;
+    let mean = table.iter().map(|entry| entry.overall_rating).sum::<f64>() / n;
+    let variance = table
+        .iter()
+        .map(|entry| (entry.overall_rating - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    format!(
+        "OVR mean {}, standard deviation {} ({} teams)",
+        format_rating(mean, precision),
+        format_rating(variance.sqrt(), precision),
+        table.len()
+    )
+}
+
+fn format_rating(rating: f64, precision: usize) -> String {
+    format!("{:.*}", precision, rating)
+}
+
+fn format_optional_rank(rank: &Option<usize>) -> String {
+    match rank {
+        Some(rank) => rank.to_string(),
+        None => "-".to_string(),
+    }
+}
+
+fn format_optional_text(text: &Option<String>) -> String {
+    text.clone().unwrap_or_default()
+}
+
+fn format_optional_count(count: &Option<usize>) -> String {
+    count.map(|count| count.to_string()).unwrap_or_default()
+}
+
+/// Counted wins over opponents whose overall rating is above `threshold`. Opponents that weren't
+/// rated never count.
+fn quality_wins(
+    rating: &TeamRating,
+    overall_ratings: &HashMap<&str, f64>,
+    threshold: f64,
+) -> usize {
+    rating
+        .breakdown
+        .iter()
+        .filter(|game| game.points_scored > game.points_allowed)
+        .filter(|game| {
+            overall_ratings
+                .get(game.opponent_id.as_str())
+                .is_some_and(|opponent_rating| *opponent_rating > threshold)
+        })
+        .count()
+}
+
+/// Places gained since the baseline run: "+3" for a team that moved up, "=" for no change, and
+/// "NEW" for a team the baseline didn't rank.
+fn rank_movement(baseline_rank: Option<usize>, rank: usize) -> String {
+    let Some(baseline_rank) = baseline_rank else {
+        return "NEW".to_string();
+    };
+    match baseline_rank.cmp(&rank) {
+        Ordering::Greater => format!("+{}", baseline_rank - rank),
+        Ordering::Less => format!("-{}", rank - baseline_rank),
+        Ordering::Equal => "=".to_string(),
+    }
+}
+
+fn format_optional_rating(rating: Option<f64>, precision: usize) -> String {
+    match rating {
+        Some(rating) => format_rating(rating, precision),
+        None => "-".to_string(),
+    }
+}
+
+fn compact(table: &[TableEntry], signed: bool) -> String {
+    table
+        .iter()
+        .map(|entry| {
+            let rating = match signed {
+                true => format!("{:+.*}", entry.precision, entry.overall_rating),
+                false => format_rating(entry.overall_rating, entry.precision),
+            };
+            format!("{}. {} ({})", entry.rank, entry.team, rating)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn delimited(table: &[TableEntry], delimiter: char) -> String {
+    table_records(table)
+        .iter()
+        .map(|record| delimited_line(record, delimiter))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn delimited_line(fields: &[String], delimiter: char) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            if field.contains(delimiter) || field.contains('"') || field.contains('\n') {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(&delimiter.to_string())
+}
+
+const EXIT_DROPPED_TEAMS: u8 = 3;
+const EXIT_INTERRUPTED: u8 = 4;
+
+/// What a shell reports for a process killed by SIGINT.
+const EXIT_SIGINT: i32 = 130;
+
+#[tokio::main]
+async fn main() -> Result<ExitCode> {
+    let mut args = Args::parse();
+
+    let mut logger = env_logger::Builder::new();
+    match std::env::var_os("RUST_LOG") {
+        Some(_) => logger.parse_default_env(),
+        None => logger.filter_level(LevelFilter::Warn).filter_module(
+            module_path!(),
+            match args.verbose {
+                0 => LevelFilter::Warn,
+                1 => LevelFilter::Info,
+                2 => LevelFilter::Debug,
+                _ => LevelFilter::Trace,
+            },
+        ),
+    };
+    logger.format_timestamp(None).init();
+
+    if let Some(Command::Compare { first, second }) = &args.command {
+        args.compare = Some((first.clone(), second.clone()));
+    }
+
+    args.season = match args.season_arg {
+        Season::Year(year) => year,
+        Season::Auto => {
+            let (start_month, labeled_by_end_year) = season_convention(&args.sport);
+            let season = season_for_date(
+                Local::now().date_naive(),
+                args.season_start_month.unwrap_or(start_month),
+                labeled_by_end_year,
+            );
+            info!("using the {} season (--season auto)", season);
+            season
+        }
+    };
+
+    if args.about_data {
+        println!("{}", methodology(&args));
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let quiet = args.quiet || !std::io::stderr().is_terminal();
+
+    let client = build_client(&args)?;
+
+    let (cache, teams_cache) = match args.no_cache {
+        true => (None, None),
+        false => (
+            Cache::new("responses", Duration::from_secs(args.cache_ttl * 60 * 60))?,
+            Cache::new(
+                "teams",
+                Duration::from_secs(args.teams_cache_ttl * 24 * 60 * 60),
+            )?,
+        ),
+    };
+
+    let fetcher = Fetcher {
+        client,
+        cache,
+        max_retries: args.max_retries,
+    };
+
+    let endpoints = args.endpoints();
+
+    let provider: Arc<dyn ScheduleProvider> = Arc::new(EspnProvider {
+        fetcher: fetcher.clone(),
+        endpoints: args.endpoints(),
+        teams_cache,
+        max_concurrency: args.max_concurrency,
+        quiet,
+    });
+
+    let discovery_start = Instant::now();
+
+    let team_ids = match &args.team_ids_file {
+        Some(path) => read_team_ids(path)?,
+        None => {
+            // Teams in more than one of the requested groups are only rated once, in the position
+            // of the first group that lists them.
+            let mut team_ids: Vec<String> = vec![];
+            let mut seen: HashSet<String> = HashSet::new();
+            for group in args.groups() {
+                let group_team_ids = provider.team_ids(args.season, group.copied()).await?;
+                team_ids.extend(
+                    group_team_ids
+                        .into_iter()
+                        .filter(|team_id| seen.insert(team_id.clone())),
+                );
+            }
+            team_ids
+        }
+    };
+
+    let discovery_time = discovery_start.elapsed();
+
+    if args.dump_team_ids {
+        for team_id in &team_ids {
+            println!("{}", team_id);
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Teams) = args.command {
+        let teams = get_teams(&fetcher, &endpoints, args.max_concurrency, &team_ids, quiet).await?;
+        let output = teams
+            .iter()
+            .map(|team| format!("{}\t{}", team.id, team.name(args.name_field)))
+            .collect::<Vec<String>>()
+            .join("\n");
+        match &args.output {
+            Some(path) => write_output(path, &output)?,
+            None => println!("{}", output),
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let sources: Vec<(String, String)> = team_ids
+        .par_iter()
+        .progress_with(progress_bar(team_ids.len(), "Generating URLs", quiet)?)
+        .map(|team_id| {
+            (
+                team_id.clone(),
+                provider.schedule_source(team_id, args.season),
+            )
+        })
+        .collect();
+
+    if args.dry_run {
+        println!(
+            "{} teams, {} schedule requests:",
+            team_ids.len(),
+            sources.len()
+        );
+        for (_, source) in &sources {
+            println!("{}", source);
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    info!(
+        "fetching {} schedules, up to {} at a time",
+        sources.len(),
+        args.max_concurrency
+    );
+
+    let fetch_start = Instant::now();
+
+    let pb = progress_bar(sources.len(), "Fetching scores", quiet)?;
+
+    let mut schedule_results = pb
+        .wrap_stream(stream::iter(sources))
+        .map(|(team_id, source)| {
+            let provider = provider.clone();
+            let season = args.season;
+            let task = tokio::spawn({
+                let team_id = team_id.clone();
+                async move { provider.schedule(&team_id, season).await }
+            });
+            async move { (team_id, source, task.await) }
+        })
+        .buffer_unordered(args.max_concurrency);
+
+    let mut team_schedules: Vec<TeamSchedule> = vec![];
+    let mut fetched_team_ids: HashSet<String> = HashSet::new();
+    let mut dropped_teams: usize = 0;
+
+    // On Ctrl-C, stop fetching and rate whatever schedules have arrived so far.
+    let interrupt = tokio::signal::ctrl_c();
+    tokio::pin!(interrupt);
+    let mut interrupt_pending = true;
+    let mut interrupted = false;
+
+    loop {
+        let schedule_result = tokio::select! {
+            result = &mut interrupt, if interrupt_pending => {
+                interrupt_pending = false;
+                if result.is_ok() {
+                    interrupted = true;
+                    break;
+                }
+                continue;
+            }
+            schedule_result = schedule_results.next() => match schedule_result {
+                Some(schedule_result) => schedule_result,
+                None => break,
+            },
+        };
+        match schedule_result {
+            // Two requested ids can lead to the same team, which must only be rated once.
+            (team_id, _, Ok(Ok(team_schedule))) => {
+                match fetched_team_ids.insert(team_schedule.team.id.clone()) {
+                    true => team_schedules.push(team_schedule),
+                    false => debug!(
+                        "skipping a second schedule for team {} (requested as team {})",
+                        team_schedule.team.id, team_id
+                    ),
+                }
+            }
+            (team_id, source, Ok(Err(e))) => {
+                if args.strict {
+                    return Err(e.context(format!(
+                        "failed to fetch schedule for team {} ({})",
+                        team_id, source
+                    )));
+                }
+                pb.suspend(|| {
+                    eprintln!("Warning: dropping team {} ({}): {:#}", team_id, source, e)
+                });
+                dropped_teams += 1;
+            }
+            // A panic is a bug in parsing or rating rather than a network problem, so it is
+            // reported as such instead of being folded into the usual dropped-team warning.
+            (team_id, source, Err(e)) => {
+                let reason = match e.try_into_panic() {
+                    Ok(payload) => {
+                        format!("the task panicked: {}", panic_message(payload.as_ref()))
+                    }
+                    Err(e) => e.to_string(),
                 };
-                Some(team_id)
+                if args.strict {
+                    bail!(
+                        "failed to fetch schedule for team {} ({}): {}",
+                        team_id,
+                        source,
+                        reason
+                    );
+                }
+                pb.suspend(|| {
+                    eprintln!(
+                        "Error: dropping team {} ({}) because {}; this is a bug, please report it",
+                        team_id, source, reason
+                    )
+                });
+                dropped_teams += 1;
+            }
+        }
+    }
+
+    drop(schedule_results);
+
+    let fetch_time = fetch_start.elapsed();
+
+    info!(
+        "fetched {} of {} schedules",
+        team_schedules.len(),
+        team_ids.len()
+    );
+
+    // Listening for Ctrl-C replaced its default of ending the process for good, so restore that for
+    // the rest of the run: there's nothing partial left to salvage after fetching.
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(EXIT_SIGINT);
+        }
+    });
+
+    // An interrupted bar stays up to show how far fetching got.
+    if interrupted {
+        pb.abandon();
+        if team_schedules.is_empty() {
+            bail!("interrupted before any schedules were fetched");
+        }
+    } else {
+        pb.finish_and_clear();
+    }
+
+    if team_schedules.is_empty() {
+        bail!(
+            "found {} teams but none of their schedules could be fetched",
+            team_ids.len()
+        );
+    }
+
+    let fbs_team_ids: Vec<&str> = team_schedules
+        .par_iter()
+        .progress_with(progress_bar(
+            team_schedules.len(),
+            "Extracting FBS team IDs",
+            quiet,
+        )?)
+        .map(|team_schedule| team_schedule.team.id.as_str())
+        .collect();
+
+    let pb = progress_bar(team_schedules.len(), "Calculating ratings", quiet)?;
+
+    let rating_options = args.rating_options();
+
+    // With --include-all-opponents the rating code logs these games itself.
+    if !args.include_all_opponents {
+        let dropped_team_ids: HashSet<&str> = team_ids
+            .iter()
+            .map(String::as_str)
+            .filter(|team_id| !fbs_team_ids.contains(team_id))
+            .collect();
+        log_dropped_opponents(&team_schedules, &dropped_team_ids, &rating_options);
+    }
+
+    if let Some(query) = &args.audit {
+        pb.finish_and_clear();
+        let team_schedule = team_schedules
+            .iter()
+            .find(|team_schedule| {
+                team_schedule.team.id == *query
+                    || team_schedule
+                        .team
+                        .name(args.name_field)
+                        .eq_ignore_ascii_case(query)
             })
+            .ok_or_else(|| anyhow!("no fetched team matches \"{}\"", query))?;
+        let output = audit(
+            team_schedule,
+            &team_schedules,
+            &fbs_team_ids,
+            &rating_options,
+            &args.style,
+        );
+        match &args.output {
+            Some(path) => write_output(path, &output)?,
+            None => println!("{}", output),
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let rating_start = Instant::now();
+    let team_ratings =
+        compute_ratings_with_progress(&team_schedules, &fbs_team_ids, &rating_options, &pb);
+
+    pb.finish_and_clear();
+    let rating_time = rating_start.elapsed();
+
+    let prediction_error = match args.validate {
+        true => Some(prediction_error(
+            &team_ratings,
+            args.defense_weight,
+            args.offense_weight,
+        )),
+        false => None,
+    };
+
+    let overall_ratings: HashMap<&str, f64> = team_ratings
+        .iter()
+        .map(|rating| {
+            (
+                rating.id.as_str(),
+                rating.weighted_rating(args.defense_weight, args.offense_weight),
+            )
+        })
+        .collect();
+    let quality_threshold = args.quality_threshold.unwrap_or(match args.elo {
+        true => ELO_INITIAL_RATING,
+        false => 0.0,
+    });
+
+    let mut table: Vec<TableEntry> = vec![];
+
+    for rating in team_ratings
+        .iter()
+        .filter(|rating| rating.games >= args.min_games)
+    {
+        table.push(TableEntry {
+            id: rating.id.clone(),
+            conference_id: rating.conference_id.clone(),
+            precision: args.precision,
+            rank: 0,
+            movement: None,
+            team: rating.name.clone(),
+            team_id: args.show_id.then(|| rating.id.clone()),
+            record: rating.record.to_string(),
+            quality_wins: args
+                .show_quality_wins
+                .then(|| quality_wins(rating, &overall_ratings, quality_threshold)),
+            overall_rating: rating.weighted_rating(args.defense_weight, args.offense_weight),
+            defense_rating: rating.elo.is_none().then_some(rating.defense_rating),
+            offense_rating: rating.elo.is_none().then_some(rating.offense_rating),
+            strength_of_schedule: rating.strength_of_schedule,
+            pythagorean: rating.pythagorean,
+            points_for: args.show_points.then_some(rating.points_for),
+            points_against: args.show_points.then_some(rating.points_against),
+            consistency: args.show_consistency.then_some(rating.consistency),
+        })
+    }
+
+    // Equal ratings fall back to name and then id so that the order is the same on every run.
+    table.sort_by(|e1, e2| {
+        e2.overall_rating
+            .total_cmp(&e1.overall_rating)
+            .then_with(|| e1.team.cmp(&e2.team))
+            .then_with(|| e1.id.cmp(&e2.id))
+    });
+
+    // Ties are judged on the rating as it will be printed, which is after normalization.
+    let displayed_ratings: Vec<f64> = match args.normalize {
+        Some(normalization) => {
+            let mut normalized = table.clone();
+            normalize(&mut normalized, normalization);
+            normalized.iter().map(|e| e.overall_rating).collect()
+        }
+        None => table.iter().map(|e| e.overall_rating).collect(),
+    };
+    let mut previous: Option<(String, usize)> = None;
+    for (i, (entry, rating)) in table.iter_mut().zip(displayed_ratings).enumerate() {
+        let displayed = format_rating(rating, args.precision);
+        entry.rank = match &previous {
+            Some((rating, rank)) if args.tie_ranks && *rating == displayed => *rank,
+            _ => i + 1,
+        };
+        previous = Some((displayed, entry.rank));
+    }
+
+    if let Some(path) = &args.baseline {
+        let baseline = read_baseline(path)?;
+        for entry in table.iter_mut() {
+            entry.movement = Some(rank_movement(baseline.get(&entry.id).copied(), entry.rank));
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.sqlite {
+        export_sqlite(path, &args, &table, &Utc::now())?;
+    }
+
+    if let Some(normalization) = args.normalize {
+        normalize(&mut table, normalization);
+    }
+
+    let poll_comparison = match args.compare_poll {
+        Some(poll) => {
+            let ranking = get_poll(&fetcher, &endpoints, &args.season, args.week, poll).await?;
+            Some(poll_comparison(
+                &table,
+                &ranking,
+                &args.style,
+                args.name_field,
+            ))
+        }
+        None => None,
+    };
+
+    let comparison = match &args.compare {
+        Some(compare) => {
+            let first = find_entry(&table, &compare.0)?;
+            let second = find_entry(&table, &compare.1)?;
+            let summary = compare_summary(&table[first], &table[second], &team_schedules);
+            let ids = [table[first].id.clone(), table[second].id.clone()];
+            table.retain(|entry| ids.contains(&entry.id));
+            Some(summary)
+        }
+        None => None,
+    };
+
+    let breakdown = match &args.team {
+        Some(query) => {
+            let entry = &table[find_entry(&table, query)?];
+            let rating = team_ratings
+                .iter()
+                .find(|rating| rating.id == entry.id)
+                .ok_or_else(|| anyhow!("no rating computed for team {}", entry.id))?;
+            let breakdown = team_breakdown(rating, args.precision, &args.style);
+            let id = entry.id.clone();
+            table.retain(|entry| entry.id == id);
+            Some(breakdown)
+        }
+        None => None,
+    };
+
+    let shortcut = |column| SortKey {
+        column,
+        descending: true,
+    };
+    let sort_key = match (
+        args.sort,
+        args.defense,
+        args.offense,
+        args.sos,
+        args.alphabetical,
+    ) {
+        (Some(sort_key), _, _, _, _) => Some(sort_key),
+        (None, true, _, _, _) => Some(shortcut(SortColumn::Defense)),
+        (None, _, true, _, _) => Some(shortcut(SortColumn::Offense)),
+        (None, _, _, true, _) => Some(shortcut(SortColumn::StrengthOfSchedule)),
+        (None, _, _, _, true) => Some(SortKey {
+            column: SortColumn::Team,
+            descending: false,
+        }),
+        (None, false, false, false, false) => None,
+    };
+    if let Some(sort_key) = sort_key {
+        sort_key.sort(&mut table);
+    }
+
+    if args.reverse {
+        table.reverse();
+    }
+
+    if let Some(top) = args.top.map(|top| top.count(table.len())) {
+        // --team and --compare already narrowed the table, so a short table is expected there.
+        if top > table.len() && args.team.is_none() && args.compare.is_none() {
+            eprintln!(
+                "Note: --top {} exceeds the {} rated teams, so all of them are shown",
+                top,
+                table.len()
+            );
+        }
+        table.truncate(top)
+    }
+
+    let table_mode = !args.json && matches!(args.output_format, OutputFormat::Table);
+
+    let stats = match args.stats && table_mode && !table.is_empty() {
+        true => Some(summary_stats(&table, args.precision)),
+        false => None,
+    };
+
+    let explanation = match args.explain && table_mode && !table.is_empty() {
+        true => Some(explanation(
+            &table,
+            &team_ratings,
+            args.defense_weight,
+            args.offense_weight,
+            args.precision,
+            &args.style,
+        )),
+        false => None,
+    };
+
+    // Ratings are margins centered around zero, except Elo which is centered on the initial rating.
+    let color_center =
+        args.color
+            .enabled(args.output.is_none())
+            .then_some(match (args.normalize, args.elo) {
+                (Some(Normalization::Range), _) => 50.0,
+                (Some(Normalization::ZScore), _) => 0.0,
+                (None, true) => ELO_INITIAL_RATING,
+                (None, false) => 0.0,
+            });
+
+    let mut output = if args.json_flat {
+        serde_json::to_string_pretty(&table)?
+    } else if args.json {
+        serde_json::to_string_pretty(&JsonOutput {
+            metadata: Metadata {
+                sport: &args.sport,
+                league: &args.league,
+                season: args.season,
+                group: &args.group,
+                adjust_depth: args.adjust_depth,
+                options: &rating_options,
+                defense_weight: args.defense_weight,
+                offense_weight: args.offense_weight,
+                min_games: args.min_games,
+                normalize: args.normalize,
+                generated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            },
+            teams: &table,
+        })?
+    } else if args.compact {
+        // Margin-based ratings are centered on zero, so show which side of average a team is on.
+        let signed = !args.elo && !matches!(args.normalize, Some(Normalization::Range));
+        compact(&table, signed)
+    } else if args.conference_ratings {
+        let conference_ids: Vec<&str> = table
+            .iter()
+            .filter_map(|entry| entry.conference_id.as_deref())
+            .collect();
+        let conference_names =
+            get_group_names(&fetcher, &endpoints, &args.season, &conference_ids).await?;
+        conference_ratings(&table, &conference_names, &args.style, args.precision)
+    } else if args.group_by_conference {
+        let conference_ids: Vec<&str> = table
+            .iter()
+            .filter_map(|entry| entry.conference_id.as_deref())
             .collect();
+        let conference_names =
+            get_group_names(&fetcher, &endpoints, &args.season, &conference_ids).await?;
+        group_by_conference(
+            table,
+            &conference_names,
+            &args.style,
+            color_center,
+            table_width(&args),
+        )
+    } else {
+        match args.output_format {
+            OutputFormat::Table => {
+                render_table(&table, &args.style, color_center, table_width(&args))
+            }
+            OutputFormat::Csv => delimited(&table, ','),
+            OutputFormat::Tsv => delimited(&table, '\t'),
+            // Colors and truncated names don't survive being pasted into a post.
+            OutputFormat::Markdown => format!(
+                "{}\n\n{}",
+                render_table(&table, &Style::markdown().into(), None, None),
+                markdown_footer(&args)
+            ),
+        }
+    };
+
+    if let Some(comparison) = comparison {
+        if table_mode {
+            output.push('\n');
+            output.push_str(&comparison);
+        }
+    }
+
+    if let Some(breakdown) = breakdown {
+        if table_mode {
+            output.push('\n');
+            output.push_str(&breakdown);
+        }
+    }
+
+    if let Some(explanation) = explanation {
+        output.push('\n');
+        output.push_str(&explanation);
+    }
+
+    if let Some(poll_comparison) = poll_comparison {
+        output.push('\n');
+        output.push_str(&poll_comparison);
+    }
+
+    if let Some(normalization) = args.normalize.filter(|_| table_mode) {
+        output.push('\n');
+        output.push_str(match normalization {
+            Normalization::Range => {
+                "OVR, DEF, and OFF are scaled to 0-100, from the lowest rated team to the highest"
+            }
+            Normalization::ZScore => {
+                "OVR, DEF, and OFF are z-scores, in standard deviations from the average team"
+            }
+        });
+    }
+
+    if let Some(stats) = stats {
+        output.push('\n');
+        output.push_str(&stats);
+    }
+
+    match &args.output {
+        Some(path) => write_output(path, &output)?,
+        None => println!("{}", output),
+    }
+    std::io::stdout().flush()?;
+
+    match prediction_error {
+        Some(Some(error)) => eprintln!(
+            "Validation: OVR differences missed the actual margin by {} points on average over {} games",
+            format_rating(error.mean_absolute_error, args.precision),
+            error.games
+        ),
+        Some(None) => eprintln!("Validation: no counted games between rated teams to check"),
+        None => {}
+    }
+
+    if args.timings {
+        eprintln!(
+            "{}",
+            timings_report(&[
+                (
+                    "Team discovery",
+                    discovery_time,
+                    format!("{} teams", team_ids.len())
+                ),
+                (
+                    "Schedule fetching",
+                    fetch_time,
+                    format!("{} schedules", team_schedules.len())
+                ),
+                (
+                    "Rating",
+                    rating_time,
+                    format!("{} teams rated", team_ratings.len())
+                ),
+            ])
+        );
+    }
+
+    if dropped_teams > 0 {
+        eprintln!(
+            "Warning: {} teams were dropped because their schedules could not be fetched",
+            dropped_teams
+        );
+    }
+
+    if interrupted {
+        eprintln!(
+            "Warning: interrupted while fetching; these ratings are partial, using {} of {} teams' schedules",
+            team_schedules.len(),
+            team_ids.len()
+        );
+    }
+
+    Ok(match (interrupted, dropped_teams > 0) {
+        (true, _) => ExitCode::from(EXIT_INTERRUPTED),
+        (false, true) => ExitCode::from(EXIT_DROPPED_TEAMS),
+        (false, false) => ExitCode::SUCCESS,
+    })
+}
+
+/// One aligned line per stage, followed by the total.
+fn timings_report(stages: &[(&str, Duration, String)]) -> String {
+    let total: Duration = stages.iter().map(|(_, elapsed, _)| *elapsed).sum();
+    let mut lines = vec!["Timings:".to_string()];
+    for (stage, elapsed, count) in stages {
+        lines.push(format!(
+            "  {:<18} {:>8.2}s  ({})",
+            stage,
+            elapsed.as_secs_f64(),
+            count
+        ));
+    }
+    lines.push(format!("  {:<18} {:>8.2}s", "Total", total.as_secs_f64()));
+    lines.join("\n")
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic payload"
+    }
+}
+
+/// Games against teams whose schedules couldn't be fetched are left out like games against teams
+/// outside the league, which thins out the affected teams' samples.
+fn log_dropped_opponents(
+    team_schedules: &[TeamSchedule],
+    dropped_team_ids: &HashSet<&str>,
+    options: &RatingOptions,
+) {
+    if dropped_team_ids.is_empty() {
+        return;
+    }
+    for team_schedule in team_schedules {
+        for event in &team_schedule.events {
+            let Some(competition) = event.competitions.last() else {
+                continue;
+            };
+            if !options.includes_game(event, competition) {
+                continue;
+            }
+            for opponent in &competition.competitors {
+                if opponent.id != team_schedule.team.id
+                    && dropped_team_ids.contains(opponent.id.as_str())
+                {
+                    debug!(
+                        "skipping {}'s game against team {}, whose schedule was not fetched",
+                        team_schedule.team.name(NameField::Location),
+                        opponent.id
+                    );
+                }
+            }
+        }
+    }
+}
+
+async fn get_teams(
+    fetcher: &Fetcher,
+    endpoints: &Endpoints,
+    max_concurrency: usize,
+    team_ids: &[String],
+    quiet: bool,
+) -> Result<Vec<Team>> {
+    let pb = progress_bar(team_ids.len(), "Fetching teams", quiet)?;
+    pb.wrap_stream(stream::iter(team_ids))
+        .map(|team_id| async move {
+            let details = fetcher
+                .fetch_json::<TeamDetails>(&endpoints.team(team_id))
+                .await
+                .with_context(|| format!("failed to fetch team {}", team_id))?;
+            Ok::<Team, anyhow::Error>(details.team)
+        })
+        .buffered(max_concurrency)
+        .try_collect()
+        .await
+}
+
+async fn get_group_names(
+    fetcher: &Fetcher,
+    endpoints: &Endpoints,
+    season: &u16,
+    group_ids: &[&str],
+) -> Result<HashMap<String, String>> {
+    let mut group_names: HashMap<String, String> = HashMap::new();
+
+    for group_id in group_ids {
+        if group_names.contains_key(*group_id) {
+            continue;
+        }
+        let group = fetcher
+            .fetch_json::<Group>(&endpoints.group(season, group_id))
+            .await?;
+        group_names.insert(group_id.to_string(), group.short_name.unwrap_or(group.name));
+    }
+
+    Ok(group_names)
+}
+
+async fn get_poll(
+    fetcher: &Fetcher,
+    endpoints: &Endpoints,
+    season: &u16,
+    week: Option<u32>,
+    poll: Poll,
+) -> Result<Ranking> {
+    let mut url = endpoints.rankings(season);
+    if let Some(week) = week {
+        url.push_str(&format!("&week={}", week));
+    }
+    let rankings = fetcher.fetch_json::<Rankings>(&url).await?;
+    let available: Vec<String> = rankings
+        .rankings
+        .iter()
+        .map(|ranking| ranking.name.clone())
+        .collect();
+    rankings
+        .rankings
+        .into_iter()
+        .find(|ranking| ranking.ranking_type == poll.espn_type())
+        .ok_or_else(|| {
+            anyhow!(
+                "no matching poll in the {} {} rankings (available: {})",
+                endpoints.league,
+                season,
+                available.join(", ")
+            )
+        })
+}
+
+/// Appends one row per team, so each run adds a new batch alongside earlier ones.
+#[cfg(feature = "sqlite")]
+fn export_sqlite(
+    path: &Path,
+    args: &Args,
+    table: &[TableEntry],
+    run_at: &chrono::DateTime<Utc>,
+) -> Result<()> {
+    let mut connection = rusqlite::Connection::open(path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    insert_ratings(&mut connection, args, table, run_at)
+        .with_context(|| format!("failed to write ratings to {}", path.display()))
+}
+
+#[cfg(feature = "sqlite")]
+fn insert_ratings(
+    connection: &mut rusqlite::Connection,
+    args: &Args,
+    table: &[TableEntry],
+    run_at: &chrono::DateTime<Utc>,
+) -> Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS ratings (
+            run_at TEXT NOT NULL,
+            sport TEXT NOT NULL,
+            league TEXT NOT NULL,
+            season INTEGER NOT NULL,
+            rank INTEGER NOT NULL,
+            team_id TEXT NOT NULL,
+            team TEXT NOT NULL,
+            overall REAL NOT NULL,
+            defense REAL,
+            offense REAL
+        )",
+        (),
+    )?;
+    let transaction = connection.transaction()?;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT INTO ratings (run_at, sport, league, season, rank, team_id, team, overall, defense, offense)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )?;
+        let run_at = run_at.to_rfc3339_opts(SecondsFormat::Secs, true);
+        for entry in table {
+            insert.execute(rusqlite::params![
+                run_at,
+                args.sport,
+                args.league,
+                args.season,
+                entry.rank as i64,
+                entry.id,
+                entry.team,
+                entry.overall_rating,
+                entry.defense_rating,
+                entry.offense_rating,
+            ])?;
+        }
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+fn write_output(path: &Path, output: &str) -> Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create directory {}", parent.display()))?;
+    }
+    fs::write(path, format!("{}\n", output))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[derive(Deserialize)]
+struct BaselineEntry {
+    rank: usize,
+    team_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BaselineJson {
+    Full { teams: Vec<BaselineEntry> },
+    Flat(Vec<BaselineEntry>),
+}
+
+/// Reads the rank of each team, by ESPN id, from an earlier run's JSON output or from CSV or TSV
+/// output with a "Team ID" column, as written with --show-id. Names aren't used because they
+/// change with --name-field and can repeat across teams.
+fn read_baseline(path: &Path) -> Result<HashMap<String, usize>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read baseline from {}", path.display()))?;
+    let trimmed = contents.trim_start();
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        let entries = match serde_json::from_str(trimmed)
+            .with_context(|| format!("failed to parse baseline JSON in {}", path.display()))?
+        {
+            BaselineJson::Full { teams } => teams,
+            BaselineJson::Flat(teams) => teams,
+        };
+        return Ok(entries
+            .into_iter()
+            .map(|entry| (entry.team_id, entry.rank))
+            .collect());
+    }
+
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header = lines.next().unwrap_or_default();
+    let delimiter = match header.contains('\t') {
+        true => '\t',
+        false => ',',
+    };
+    let headers = split_delimited_line(header, delimiter);
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let (Some(rank_column), Some(team_id_column)) = (column("#"), column("Team ID")) else {
+        bail!(
+            "{} is not a JSON, CSV, or TSV ratings file with \"#\" and \"Team ID\" columns",
+            path.display()
+        );
+    };
+    let mut baseline = HashMap::new();
+    for (i, line) in lines.enumerate() {
+        let fields = split_delimited_line(line, delimiter);
+        let (Some(rank), Some(team_id)) = (fields.get(rank_column), fields.get(team_id_column))
+        else {
+            bail!("{}:{}: missing columns", path.display(), i + 2);
+        };
+        let rank = rank
+            .parse()
+            .with_context(|| format!("{}:{}: invalid rank \"{}\"", path.display(), i + 2, rank))?;
+        baseline.insert(team_id.clone(), rank);
+    }
+    Ok(baseline)
+}
+
+/// The inverse of `delimited_line`: splits on the delimiter outside of double quotes, where a
+/// doubled quote stands for one quote.
+fn split_delimited_line(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Reads newline-delimited team IDs, ignoring blank lines.
+fn read_team_ids(path: &Path) -> Result<Vec<String>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read team IDs from {}", path.display()))?;
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(line_number, line)| match is_valid_team_id(line) {
+            true => Ok(line.to_string()),
+            false => Err(anyhow!(
+                "{}:{}: \"{}\" is not a valid team ID",
+                path.display(),
+                line_number,
+                line
+            )),
+        })
+        .collect()
+}
 
-        team_ids.append(&mut iteration_team_ids);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
 
-        if teams_response_data.page_index == teams_response_data.page_count {
-            break;
+    #[test]
+    fn recency_halflife_must_be_positive() {
+        assert_eq!(parse_halflife("7").unwrap(), 7.0);
+        assert!(parse_halflife("0").is_err());
+        assert!(parse_halflife("-7").is_err());
+        assert!(parse_halflife("NaN").is_err());
+    }
+
+    /// A table row with only what every output shows filled in.
+    fn entry(team: &str, conference_id: Option<&str>, overall_rating: f64) -> TableEntry {
+        TableEntry {
+            id: team.to_lowercase(),
+            conference_id: conference_id.map(str::to_string),
+            rank: 0,
+            movement: None,
+            team: team.to_string(),
+            team_id: None,
+            record: "0-0".to_string(),
+            overall_rating,
+            defense_rating: Some(overall_rating / 2.0),
+            offense_rating: Some(overall_rating / 2.0),
+            precision: 2,
+            strength_of_schedule: 0.0,
+            pythagorean: 0.5,
+            points_for: None,
+            points_against: None,
+            consistency: None,
+            quality_wins: None,
         }
     }
 
-    Ok(team_ids)
+    /// The team column of a plain table, top to bottom, paired with each row's rank.
+    fn ranked_teams(rendered: &str) -> Vec<(usize, String)> {
+        rendered
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                Some((fields.first()?.parse().ok()?, fields.get(1)?.to_string()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn groups_by_conference() {
+        let table = vec![
+            entry("Alpha", Some("1"), 20.0),
+            entry("Bravo", Some("2"), 15.0),
+            entry("Charlie", Some("2"), 10.0),
+            entry("Delta", Some("1"), -10.0),
+            entry("Echo", Some("2"), 5.0),
+        ];
+        let conference_names = HashMap::from([
+            ("1".to_string(), "East".to_string()),
+            ("2".to_string(), "West".to_string()),
+        ]);
+        let rendered =
+            group_by_conference(table, &conference_names, &Style::blank().into(), None, None);
+
+        // West averages 10 and East 5, so West comes first.
+        let (west, east) = rendered.split_once("\n\n").unwrap();
+        assert!(west.starts_with("West (average OVR 10.00)\n"), "{}", west);
+        assert!(east.starts_with("East (average OVR 5.00)\n"), "{}", east);
+        assert_eq!(
+            ranked_teams(west),
+            [
+                (1, "Bravo".to_string()),
+                (2, "Charlie".to_string()),
+                (3, "Echo".to_string())
+            ]
+        );
+        assert_eq!(
+            ranked_teams(east),
+            [(1, "Alpha".to_string()), (2, "Delta".to_string())]
+        );
+    }
+
+    #[test]
+    fn precision_zero_rounds_to_whole_numbers() {
+        assert_eq!(format_rating(7.49, 0), "7");
+        assert_eq!(format_rating(7.51, 0), "8");
+        assert_eq!(format_rating(-3.6, 0), "-4");
+        assert_eq!(format_rating(7.51, 3), "7.510");
+
+        let mut alpha = entry("Alpha", None, 12.6);
+        alpha.precision = 0;
+        let headers = TableEntry::headers();
+        let fields = alpha.fields();
+        let column = |header: &str| {
+            let col = headers.iter().position(|h| h == header).unwrap();
+            fields[col].to_string()
+        };
+        assert_eq!(column("OVR"), "13");
+        assert_eq!(column("DEF"), "6");
+        assert_eq!(column("OFF"), "6");
+        assert_eq!(column("PYTH"), "0.50");
+    }
+
+    #[test]
+    fn summary_stats_of_known_ratings() {
+        let table: Vec<TableEntry> = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]
+            .into_iter()
+            .map(|rating| entry("Team", None, rating))
+            .collect();
+        assert_eq!(
+            summary_stats(&table, 2),
+            "OVR mean 5.00, standard deviation 2.00 (8 teams)"
+        );
+        assert_eq!(
+            summary_stats(&table[..2], 1),
+            "OVR mean 3.0, standard deviation 1.0 (2 teams)"
+        );
+    }
+
+    #[test]
+    fn writes_output_to_a_new_directory() {
+        let dir = std::env::temp_dir().join(format!("rasor_ratings-{}-output", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("nested").join("ratings.txt");
+
+        write_output(&path, "#  Team\n1  Alpha").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "#  Team\n1  Alpha\n");
+
+        // Writing to a file never colors the table on its own.
+        assert!(!ColorChoice::Auto.enabled(false));
+        assert!(ColorChoice::Always.enabled(false));
+    }
+
+    #[test]
+    fn metadata_records_every_rating_option() {
+        let args = Args::try_parse_from([
+            "rasor_ratings",
+            "-s",
+            "football",
+            "-l",
+            "college-football",
+            "-S",
+            "2024",
+            "--name-field",
+            "display-name",
+            "--mov-transform",
+            "sqrt",
+            "--adjust-depth",
+            "3",
+            "--adjust-decay",
+            "0.25",
+        ])
+        .unwrap();
+        let options = args.rating_options();
+        let metadata = serde_json::to_value(Metadata {
+            sport: "football",
+            league: "college-football",
+            season: 2024,
+            group: &[80, 81],
+            adjust_depth: args.adjust_depth,
+            options: &options,
+            defense_weight: 1.0,
+            offense_weight: 2.0,
+            min_games: 3,
+            normalize: Some(Normalization::ZScore),
+            generated_at: "2024-12-01T00:00:00Z".to_string(),
+        })
+        .unwrap();
+
+        assert_eq!(metadata["group"], serde_json::json!([80, 81]));
+        assert_eq!(metadata["min_games"], 3);
+        assert_eq!(metadata["offense_weight"], 2.0);
+        assert_eq!(metadata["name_field"], "display-name");
+        assert_eq!(metadata["normalize"], "z-score");
+        assert_eq!(metadata["mov_transform"], "sqrt");
+        assert_eq!(metadata["metric"], "score");
+        assert_eq!(metadata["forfeit_policy"], "skip");
+        assert_eq!(metadata["model"], "opponent-average");
+        assert_eq!(metadata["adjust_depth"], 3);
+        assert_eq!(metadata["iterations"], 3);
+        assert_eq!(metadata["adjustment_decay"], 0.25);
+        assert_eq!(metadata["season_type"], "regular");
+        assert_eq!(metadata["include_all_opponents"], false);
+        assert_eq!(metadata["exclude_overtime"], false);
+        assert_eq!(metadata["pyth_exponent"], 2.37);
+        assert_eq!(metadata["elo_k_factor"], 20.0);
+        assert_eq!(metadata["week"], Value::Null);
+    }
+
+    #[test]
+    fn pf_and_pa_columns_only_show_up_when_some_entry_has_them() {
+        let mut alpha = entry("Alpha", None, 10.0);
+        let records = table_records(std::slice::from_ref(&alpha));
+        assert!(!records[0]
+            .iter()
+            .any(|header| header == "PF" || header == "PA"));
+        alpha.points_for = Some(37.5);
+        alpha.points_against = Some(18.5);
+        let records = table_records(&[alpha]);
+        let column = |header: &str| {
+            let col = records[0].iter().position(|h| h == header).unwrap();
+            records[1][col].clone()
+        };
+        assert_eq!(
+            (column("PF"), column("PA")),
+            ("37.5".to_string(), "18.5".to_string())
+        );
+    }
+
+    #[test]
+    fn normalize_range_maps_top_to_100_and_bottom_to_0() {
+        let mut table = vec![
+            entry("Alpha", None, 14.0),
+            entry("Bravo", None, 1.0),
+            entry("Charlie", None, -12.0),
+        ];
+        normalize(&mut table, Normalization::Range);
+        let overall: Vec<f64> = table.iter().map(|e| e.overall_rating).collect();
+        assert_eq!(overall, [100.0, 50.0, 0.0]);
+        let defense: Vec<Option<f64>> = table.iter().map(|e| e.defense_rating).collect();
+        assert_eq!(defense, [Some(100.0), Some(50.0), Some(0.0)]);
+    }
+
+    #[test]
+    fn normalize_z_score() {
+        let mut table: Vec<TableEntry> = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]
+            .into_iter()
+            .map(|rating| entry("Team", None, rating))
+            .collect();
+        normalize(&mut table, Normalization::ZScore);
+        assert_eq!(table[0].overall_rating, -1.5);
+        assert_eq!(table[4].overall_rating, 0.0);
+        assert_eq!(table[7].overall_rating, 2.0);
+    }
+
+    #[test]
+    fn compact_prints_one_line_per_team() {
+        let mut table = vec![
+            entry("Georgia", None, 14.234),
+            entry("Kent State", None, -20.0),
+        ];
+        table[0].rank = 1;
+        table[1].rank = 2;
+        assert_eq!(
+            compact(&table, true),
+            "1. Georgia (+14.23)\n2. Kent State (-20.00)"
+        );
+        assert_eq!(
+            compact(&table, false),
+            "1. Georgia (14.23)\n2. Kent State (-20.00)"
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn inserts_ratings_into_sqlite() {
+        let mut args =
+            Args::try_parse_from(["rasor_ratings", "-s", "football", "-l", "nfl", "-S", "2024"])
+                .unwrap();
+        // main() resolves --season into this.
+        args.season = 2024;
+        let mut table = vec![entry("Alpha", None, 12.5), entry("Bravo", None, -3.0)];
+        table[0].rank = 1;
+        table[1].rank = 2;
+        table[1].defense_rating = None;
+        let mut connection = rusqlite::Connection::open_in_memory().unwrap();
+        let first_run = "2024-10-01T12:00:00Z".parse().unwrap();
+        let second_run = "2024-10-08T12:00:00Z".parse().unwrap();
+        insert_ratings(&mut connection, &args, &table, &first_run).unwrap();
+        insert_ratings(&mut connection, &args, &table[..1], &second_run).unwrap();
+
+        let mut query = connection
+            .prepare("SELECT run_at, sport, league, season, rank, team_id, team, overall, defense, offense FROM ratings ORDER BY run_at, rank")
+            .unwrap();
+        type Row = (
+            String,
+            String,
+            String,
+            u16,
+            i64,
+            String,
+            String,
+            f64,
+            Option<f64>,
+            Option<f64>,
+        );
+        let rows: Vec<Row> = query
+            .query_map((), |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                    row.get(7)?,
+                    row.get(8)?,
+                    row.get(9)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let row = |run_at: &str, rank, team: &str, overall, defense| -> Row {
+            (
+                run_at.to_string(),
+                "football".to_string(),
+                "nfl".to_string(),
+                2024,
+                rank,
+                team.to_lowercase(),
+                team.to_string(),
+                overall,
+                defense,
+                Some(overall / 2.0),
+            )
+        };
+        assert_eq!(
+            rows,
+            [
+                row("2024-10-01T12:00:00Z", 1, "Alpha", 12.5, Some(6.25)),
+                row("2024-10-01T12:00:00Z", 2, "Bravo", -3.0, None),
+                row("2024-10-08T12:00:00Z", 1, "Alpha", 12.5, Some(6.25)),
+            ]
+        );
+    }
+
+    #[test]
+    fn numeric_columns_are_right_aligned() {
+        let mut table = vec![entry("Alpha", None, 112.5), entry("Bravo", None, -3.0)];
+        table[0].rank = 1;
+        table[1].rank = 10;
+        let rendered = render_table(&table, &Style::psql().into(), None, None);
+        let lines: Vec<&str> = rendered.lines().collect();
+        // Each cell's text ends at the same column as the header's and every other row's.
+        let end = |line: &str, text: &str| line.find(text).unwrap() + text.len();
+        assert_eq!(end(lines[2], " 1 "), end(lines[3], "10 "));
+        assert_eq!(end(lines[2], "112.50"), end(lines[3], "-3.00"));
+        assert_eq!(end(lines[0], "OVR"), end(lines[3], "-3.00"));
+        // The team column stays left-aligned.
+        assert_eq!(lines[2].find("Alpha"), lines[3].find("Bravo"));
+    }
+
+    #[test]
+    fn finds_a_team_by_id_or_name() {
+        let table = vec![entry("Alpha", None, 3.0), entry("Bravo", None, 1.0)];
+        assert_eq!(find_entry(&table, "bravo").unwrap(), 1);
+        assert_eq!(find_entry(&table, "BRAVO").unwrap(), 1);
+        assert_eq!(find_entry(&table, "alpha").unwrap(), 0);
+        assert_eq!(
+            find_entry(&table, "Charlie").unwrap_err().to_string(),
+            "no ranked team matches \"Charlie\""
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_a_panicking_task() {
+        let literal = tokio::spawn(async { panic!("bad competitor") }).await;
+        let formatted = tokio::spawn(async { panic!("bad competitor {}", 7) }).await;
+        let custom = tokio::spawn(async { std::panic::panic_any(7) }).await;
+        let messages: Vec<String> = [literal, formatted, custom]
+            .into_iter()
+            .map(|result: Result<(), tokio::task::JoinError>| {
+                let payload = result.unwrap_err().try_into_panic().unwrap();
+                panic_message(payload.as_ref()).to_string()
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "bad competitor",
+                "bad competitor 7",
+                "unknown panic payload"
+            ]
+        );
+    }
+
+    #[test]
+    fn top_must_be_positive() {
+        assert!(matches!(parse_top("10").unwrap(), Top::Count(10)));
+        assert_eq!(
+            parse_top("0").unwrap_err().to_string(),
+            "must be at least 1 (leave out --top to show every team)"
+        );
+    }
+
+    #[test]
+    fn percent_top_rounds_up() {
+        let top = parse_top("25%").unwrap();
+        assert!(matches!(top, Top::Percent(25)));
+        assert_eq!(top.count(40), 10);
+        assert_eq!(top.count(41), 11);
+        assert_eq!(parse_top("1%").unwrap().count(3), 1);
+        assert_eq!(parse_top("100%").unwrap().count(40), 40);
+        assert_eq!(
+            parse_top("101%").unwrap_err().to_string(),
+            "can't be more than 100%"
+        );
+    }
+
+    #[test]
+    fn season_for_a_fixed_date() {
+        let date = |year, month, day| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        let (start_month, labeled_by_end_year) = season_convention("football");
+        assert_eq!(
+            season_for_date(date(2025, 2, 10), start_month, labeled_by_end_year),
+            2024
+        );
+        assert_eq!(
+            season_for_date(date(2025, 9, 1), start_month, labeled_by_end_year),
+            2025
+        );
+        let (start_month, labeled_by_end_year) = season_convention("basketball");
+        assert_eq!(
+            season_for_date(date(2025, 2, 10), start_month, labeled_by_end_year),
+            2025
+        );
+        assert_eq!(
+            season_for_date(date(2025, 11, 1), start_month, labeled_by_end_year),
+            2026
+        );
+    }
+
+    #[test]
+    fn rank_movement_against_a_baseline() {
+        let path =
+            std::env::temp_dir().join(format!("rasor_ratings-{}-baseline.csv", std::process::id()));
+        fs::write(
+            &path,
+            "#,Team,Team ID,OVR\n1,Alpha,1,19.33\n2,\"Bravo, B\",2,-2.33\n3,Charlie,3,-7.00\n",
+        )
+        .unwrap();
+        let baseline = read_baseline(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let movement: Vec<String> = [("3", 1), ("1", 2), ("2", 3), ("4", 4)]
+            .into_iter()
+            .map(|(team_id, rank)| rank_movement(baseline.get(team_id).copied(), rank))
+            .collect();
+        assert_eq!(movement, ["+2", "-1", "-1", "NEW"]);
+        assert_eq!(rank_movement(Some(5), 5), "=");
+    }
+
+    #[test]
+    fn reads_a_json_baseline() {
+        let path = std::env::temp_dir().join(format!(
+            "rasor_ratings-{}-baseline.json",
+            std::process::id()
+        ));
+        fs::write(
+            &path,
+            r#"{"metadata": {}, "teams": [{"team_id": "1", "rank": 1, "team": "Alpha"}, {"team_id": "2", "rank": 2, "team": "Bravo"}]}"#,
+        )
+        .unwrap();
+        let baseline = read_baseline(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            baseline,
+            HashMap::from([("1".to_string(), 1), ("2".to_string(), 2)])
+        );
+    }
+
+    #[test]
+    fn a_baseline_without_team_ids_is_an_error() {
+        let path = std::env::temp_dir().join(format!(
+            "rasor_ratings-{}-baseline-by-name.csv",
+            std::process::id()
+        ));
+        fs::write(&path, "#,Team,OVR\n1,Alpha,19.33\n").unwrap();
+        let error = read_baseline(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(error.to_string().contains("\"Team ID\""), "{}", error);
+    }
+
+    /// The header lines, lowercased, of the one request `client` makes to a local listener.
+    async fn request_headers(client: &Client) -> Vec<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut headers = vec![];
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                headers.push(line.trim_end().to_lowercase());
+                line.clear();
+            }
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}");
+            headers
+        });
+        client.get(url).send().await.unwrap();
+        server.join().unwrap()
+    }
+
+    #[tokio::test]
+    async fn asks_for_compression_unless_told_not_to() {
+        let args = |extra: &[&str]| {
+            let mut args = vec!["rasor_ratings", "-s", "football", "-l", "nfl", "-S", "2024"];
+            args.extend(extra);
+            Args::try_parse_from(args).unwrap()
+        };
+
+        let headers = request_headers(&build_client(&args(&[])).unwrap()).await;
+        let accept_encoding = headers
+            .iter()
+            .find_map(|header| header.strip_prefix("accept-encoding: "))
+            .unwrap();
+        assert!(accept_encoding.contains("gzip"), "{}", accept_encoding);
+        assert!(accept_encoding.contains("deflate"), "{}", accept_encoding);
+
+        let headers = request_headers(&build_client(&args(&["--no-compression"])).unwrap()).await;
+        assert!(
+            !headers
+                .iter()
+                .any(|header| header.starts_with("accept-encoding:")),
+            "{:?}",
+            headers
+        );
+    }
+
+    #[test]
+    fn ranks_conferences_by_average_rating() {
+        let table = vec![
+            entry("Alpha", Some("1"), 20.0),
+            entry("Bravo", Some("2"), 15.0),
+            entry("Charlie", Some("2"), 10.0),
+            entry("Delta", Some("1"), -10.0),
+            entry("Echo", Some("2"), -1.0),
+            entry("Foxtrot", Some("3"), 2.0),
+        ];
+        let conference_names = HashMap::from([
+            ("1".to_string(), "East".to_string()),
+            ("2".to_string(), "West".to_string()),
+        ]);
+        let rendered = conference_ratings(&table, &conference_names, &Style::blank().into(), 2);
+        let rows: Vec<Vec<&str>> = rendered
+            .lines()
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        // Foxtrot's conference has no name, so it's listed as "Other".
+        assert_eq!(rows[0], ["#", "Conference", "Teams", "AVG", "MED"]);
+        assert_eq!(rows[1], ["1", "West", "3", "8.00", "10.00"]);
+        assert_eq!(rows[2], ["2", "East", "2", "5.00", "5.00"]);
+        assert_eq!(rows[3], ["3", "Other", "1", "2.00", "2.00"]);
+        assert_eq!(rows.len(), 4);
+    }
+
+    #[test]
+    fn timings_report_aligns_stages_and_totals_them() {
+        let report = timings_report(&[
+            (
+                "Team discovery",
+                Duration::from_millis(250),
+                "4 teams".to_string(),
+            ),
+            (
+                "Rating",
+                Duration::from_millis(1500),
+                "4 teams rated".to_string(),
+            ),
+        ]);
+        assert_eq!(
+            report,
+            "Timings:\n  Team discovery         0.25s  (4 teams)\n  Rating                 1.50s  (4 teams rated)\n  Total                  1.75s"
+        );
+    }
+
+    #[test]
+    fn width_truncates_long_team_names() {
+        let table = vec![
+            entry(
+                "The Extraordinarily Long-Named University of Somewhere",
+                None,
+                20.0,
+            ),
+            entry("Short", None, -20.0),
+        ];
+        let width = |rendered: &str| rendered.lines().map(|line| line.chars().count()).max();
+        let style: Theme = Style::psql().into();
+
+        let unconstrained = render_table(&table, &style, None, None);
+        assert!(width(&unconstrained).unwrap() > 60, "{}", unconstrained);
+        let rendered = render_table(&table, &style, None, Some(60));
+        assert!(width(&rendered).unwrap() <= 60, "{}", rendered);
+        assert!(rendered.contains("Short"));
+        assert!(rendered.contains("20.00"));
+    }
 }
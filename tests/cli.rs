@@ -0,0 +1,770 @@
+// SPDX-FileCopyrightText: 2024 Sebastian Rasor <https://www.sebastianrasor.com/contact>
+// SPDX-License-Identifier: AGPL-3.0-only
+
+mod common;
+
+use common::{MockServer, Reply};
+use std::process::{Command, Output};
+
+/// Runs the command line against `server` for sport `f`, league `l`, and the 2024 season, without
+/// touching the cache, retrying, or picking up RUST_LOG.
+fn rasor_ratings(server: &MockServer, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rasor_ratings"))
+        .env_remove("RUST_LOG")
+        .args(["-s", "f", "-l", "l", "-S", "2024", "--no-cache", "-q"])
+        .args(["--max-retries", "0"])
+        .args([
+            "--site-api-base",
+            &server.url,
+            "--core-api-base",
+            &server.url,
+        ])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+/// The rows of CSV output, without the header.
+fn csv_rows(output: &Output) -> Vec<Vec<String>> {
+    stdout(output)
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').map(str::to_string).collect())
+        .collect()
+}
+
+/// The four-team league, except that team B's schedule is not valid JSON.
+fn malformed_schedule_server() -> MockServer {
+    MockServer::start(|path, _| match path == common::schedule_path("B") {
+        true => Reply::raw(200, "{\"team\": "),
+        false => common::espn(path, &["A", "B", "C", "D"], &common::league()),
+    })
+}
+
+#[test]
+fn drops_a_team_whose_schedule_does_not_parse() {
+    let output = rasor_ratings(&malformed_schedule_server(), &[]);
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("Warning: dropping team B"), "{}", stderr);
+    assert!(
+        stderr.contains("1 teams were dropped because their schedules could not be fetched"),
+        "{}",
+        stderr
+    );
+    assert!(stdout(&output).contains("Team A"));
+}
+
+#[test]
+fn strict_fails_on_a_schedule_that_does_not_parse() {
+    let output = rasor_ratings(&malformed_schedule_server(), &["--strict"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("failed to fetch schedule for team B"),
+        "{}",
+        stderr
+    );
+    assert!(stdout(&output).is_empty());
+}
+
+#[test]
+fn min_games_drops_teams_with_too_few_games() {
+    // Teams A through E each played the other four, and team F only played team A.
+    let team_ids = ["A", "B", "C", "D", "E", "F"];
+    let mut games = vec![];
+    for (i, home) in team_ids[..5].iter().enumerate() {
+        for (j, away) in team_ids[i + 1..5].iter().enumerate() {
+            let day = (7 * i + j) as u64;
+            games.push(common::game(day, (home, 20 + 3 * j as u32), (away, 17)));
+        }
+    }
+    games.push(common::game(50, ("A", 28), ("F", 3)));
+    let server = MockServer::start(move |path, _| common::espn(path, &team_ids, &games));
+
+    let rows = csv_rows(&rasor_ratings(&server, &["-f", "csv"]));
+    assert!(rows.iter().any(|row| row[1] == "Team F"));
+
+    let rows = csv_rows(&rasor_ratings(&server, &["-f", "csv", "--min-games", "4"]));
+    let teams: Vec<&str> = rows.iter().map(|row| row[1].as_str()).collect();
+    assert_eq!(teams.len(), 5);
+    assert!(!teams.contains(&"Team F"));
+    let ranks: Vec<&str> = rows.iter().map(|row| row[0].as_str()).collect();
+    assert_eq!(ranks, ["1", "2", "3", "4", "5"]);
+}
+
+#[test]
+fn fails_when_no_teams_are_listed() {
+    let server = MockServer::start(|path, _| common::espn(path, &[], &[]));
+    let output = rasor_ratings(&server, &[]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("no teams found for sport \"f\", league \"l\", season 2024"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("check the --sport, --league, and --season values"));
+    assert!(stdout(&output).is_empty());
+}
+
+#[test]
+fn fails_when_no_schedules_can_be_fetched() {
+    let server = MockServer::start(|path, _| match path.starts_with(common::TEAMS_PATH) {
+        true => common::espn(path, &["A", "B", "C"], &[]),
+        false => Reply::status(500),
+    });
+    let output = rasor_ratings(&server, &[]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("found 3 teams but none of their schedules could be fetched"),
+        "{}",
+        stderr
+    );
+}
+
+fn league_server() -> MockServer {
+    MockServer::start(|path, _| common::espn(path, &["A", "B", "C", "D"], &common::league()))
+}
+
+/// The team column of CSV output, top to bottom.
+fn teams(output: &Output) -> Vec<String> {
+    csv_rows(output)
+        .into_iter()
+        .map(|row| row[1].clone())
+        .collect()
+}
+
+#[test]
+fn offense_weight_reorders_teams() {
+    let server = league_server();
+    // Team C rates 3.17 DEF and -10.17 OFF, while team D rates -7.33 and -2.67.
+    assert_eq!(
+        teams(&rasor_ratings(&server, &["-f", "csv"])),
+        ["Team A", "Team B", "Team C", "Team D"]
+    );
+    let output = rasor_ratings(&server, &["-f", "csv", "--offense-weight", "2"]);
+    assert_eq!(teams(&output), ["Team A", "Team B", "Team D", "Team C"]);
+    let overall: Vec<String> = csv_rows(&output)
+        .into_iter()
+        .map(|row| row[3].clone())
+        .collect();
+    assert_eq!(overall, ["30.67", "-0.83", "-12.67", "-17.17"]);
+}
+
+#[test]
+fn show_points_averages_points_for_and_against() {
+    let games = vec![
+        common::game(0, ("A", 35), ("B", 17)),
+        common::game(1, ("C", 20), ("D", 10)),
+        common::game(7, ("A", 40), ("C", 20)),
+        common::game(8, ("B", 24), ("D", 21)),
+        common::game(14, ("D", 14), ("A", 30)),
+        common::game(15, ("B", 20), ("C", 10)),
+    ];
+    let server =
+        MockServer::start(move |path, _| common::espn(path, &["A", "B", "C", "D"], &games));
+
+    let output = rasor_ratings(&server, &["-f", "csv", "--show-points"]);
+    let lines: Vec<String> = stdout(&output).lines().map(str::to_string).collect();
+    let headers: Vec<&str> = lines[0].split(',').collect();
+    let row = csv_rows(&output)
+        .into_iter()
+        .find(|row| row[1] == "Team A")
+        .unwrap();
+    let column = |header| row[headers.iter().position(|h| *h == header).unwrap()].as_str();
+    assert_eq!((column("PF"), column("PA")), ("35.0", "17.0"));
+
+    let output = rasor_ratings(&server, &["-f", "csv"]);
+    assert!(!stdout(&output).contains("PF"));
+}
+
+#[test]
+fn ties_are_ordered_by_name() {
+    // Teams B and C had the same results against the same teams, and tied each other.
+    let games = vec![
+        common::game(0, ("A", 20), ("C", 10)),
+        common::game(1, ("A", 20), ("B", 10)),
+        common::game(7, ("C", 20), ("D", 10)),
+        common::game(8, ("B", 20), ("D", 10)),
+        common::game(14, ("C", 14), ("B", 14)),
+        common::game(15, ("A", 30), ("D", 0)),
+    ];
+    let server =
+        MockServer::start(move |path, _| common::espn(path, &["A", "C", "B", "D"], &games));
+
+    for _ in 0..3 {
+        let rows = csv_rows(&rasor_ratings(&server, &["-f", "csv", "--precision", "15"]));
+        assert_eq!(rows[1][3], rows[2][3]);
+        let teams: Vec<&str> = rows.iter().map(|row| row[1].as_str()).collect();
+        assert_eq!(teams, ["Team A", "Team B", "Team C", "Team D"]);
+    }
+}
+
+#[test]
+fn exit_code_reflects_dropped_teams() {
+    let output = rasor_ratings(&league_server(), &[]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let server = MockServer::start(|path, _| match path == common::schedule_path("D") {
+        true => Reply::status(500),
+        false => common::espn(path, &["A", "B", "C", "D"], &common::league()),
+    });
+    let output = rasor_ratings(&server, &[]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stdout(&output).contains("Team A"));
+}
+
+#[test]
+fn output_writes_only_the_rendered_table_to_the_file() {
+    let server = league_server();
+    let path = common::temp_dir("output").join("ratings.txt");
+    let table = stdout(&rasor_ratings(&server, &["--color", "never"]));
+
+    let output = rasor_ratings(
+        &server,
+        &["--color", "never", "--output", path.to_str().unwrap()],
+    );
+    assert!(output.status.success());
+    assert!(stdout(&output).is_empty());
+    // The table, which ends with its last row, and one newline, as `println!` would have written.
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(contents, table);
+    let rendered = contents.strip_suffix('\n').unwrap();
+    assert!(!rendered.ends_with('\n'));
+    assert_eq!(rendered.lines().count(), 2 + 4);
+    assert!(rendered.lines().last().unwrap().contains("Team D"));
+}
+
+#[test]
+fn ctrl_c_rates_the_schedules_fetched_so_far() {
+    // Team D's schedule never arrives.
+    let server = MockServer::start(|path, _| {
+        if path == common::schedule_path("D") {
+            std::thread::sleep(std::time::Duration::from_secs(60));
+        }
+        common::espn(path, &["A", "B", "C", "D"], &common::league())
+    });
+    let child = Command::new(env!("CARGO_BIN_EXE_rasor_ratings"))
+        .args(["-s", "f", "-l", "l", "-S", "2024", "--no-cache", "-q"])
+        .args([
+            "--site-api-base",
+            &server.url,
+            "--core-api-base",
+            &server.url,
+        ])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    while server.hits(&common::schedule_path("D")) == 0 {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    let killed = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("these ratings are partial, using 3 of 4 teams' schedules"),
+        "{}",
+        stderr
+    );
+    assert!(stdout(&output).contains("Team A"));
+}
+
+#[test]
+fn keeps_alphanumeric_team_ids() {
+    let games = vec![
+        common::game(0, ("25", 30), ("usc", 10)),
+        common::game(7, ("usc", 24), ("TX-A_2", 21)),
+        common::game(14, ("TX-A_2", 7), ("25", 35)),
+    ];
+    let server =
+        MockServer::start(move |path, _| common::espn(path, &["25", "usc", "TX-A_2"], &games));
+    let output = rasor_ratings(&server, &["-f", "csv"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(teams(&output), ["Team 25", "Team usc", "Team TX-A_2"]);
+}
+
+#[test]
+fn verbose_logs_games_against_dropped_teams() {
+    let server = malformed_schedule_server();
+    let message = "skipping Team A's game against team B, whose schedule was not fetched";
+    assert!(!stderr(&rasor_ratings(&server, &["-v"])).contains(message));
+    let stderr = stderr(&rasor_ratings(&server, &["-vv"]));
+    assert!(stderr.contains(message), "{}", stderr);
+}
+
+#[test]
+fn verbose_logs_progress_unless_rust_log_is_set() {
+    let server = league_server();
+    let message = "fetched 4 of 4 schedules";
+    assert!(!stderr(&rasor_ratings(&server, &[])).contains(message));
+    let verbose = stderr(&rasor_ratings(&server, &["-v"]));
+    assert!(verbose.contains(message), "{}", verbose);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rasor_ratings"))
+        .env("RUST_LOG", "warn")
+        .args(["-s", "f", "-l", "l", "-S", "2024", "--no-cache", "-q", "-v"])
+        .args([
+            "--site-api-base",
+            &server.url,
+            "--core-api-base",
+            &server.url,
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!stderr(&output).contains(message), "{}", stderr(&output));
+}
+
+#[test]
+fn cached_team_ids_skip_pagination_but_not_schedules() {
+    let server = league_server();
+    let cache_home = common::temp_dir("teams-cache");
+    for _ in 0..2 {
+        let output = Command::new(env!("CARGO_BIN_EXE_rasor_ratings"))
+            .env("XDG_CACHE_HOME", &cache_home)
+            .args(["-s", "f", "-l", "l", "-S", "2024", "-q", "--cache-ttl", "0"])
+            .args([
+                "--site-api-base",
+                &server.url,
+                "--core-api-base",
+                &server.url,
+            ])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(stdout(&output).contains("Team A"));
+    }
+    assert_eq!(server.hits(&common::teams_page_path(1)), 1);
+    assert_eq!(server.hits(&common::schedule_path("A")), 2);
+}
+
+#[test]
+fn sort_off_desc_matches_offense() {
+    let server = league_server();
+    let sorted = rasor_ratings(&server, &["-f", "csv", "--sort", "off:desc"]);
+    let offense = rasor_ratings(&server, &["-f", "csv", "--offense"]);
+    assert_eq!(stdout(&sorted), stdout(&offense));
+    assert_eq!(teams(&sorted), ["Team A", "Team B", "Team D", "Team C"]);
+
+    let sorted = rasor_ratings(&server, &["-f", "csv", "--sort", "def:asc"]);
+    assert_eq!(teams(&sorted), ["Team D", "Team B", "Team C", "Team A"]);
+}
+
+#[test]
+fn dry_run_fetches_no_schedules() {
+    let server = league_server();
+    let output = rasor_ratings(&server, &["--dry-run"]);
+    assert_eq!(output.status.code(), Some(0));
+    let expected: Vec<String> = std::iter::once("4 teams, 4 schedule requests:".to_string())
+        .chain(
+            ["A", "B", "C", "D"]
+                .iter()
+                .map(|team_id| format!("{}{}", server.url, common::schedule_path(team_id))),
+        )
+        .collect();
+    assert_eq!(stdout(&output).lines().collect::<Vec<_>>(), expected);
+    assert_eq!(server.hits(&common::teams_page_path(1)), 1);
+    assert_eq!(server.total_hits(), 1);
+}
+
+#[test]
+fn top_zero_is_an_error() {
+    let output = rasor_ratings(&league_server(), &["--top", "0"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("must be at least 1 (leave out --top to show every team)"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn top_beyond_the_table_shows_every_team_with_a_note() {
+    let output = rasor_ratings(&league_server(), &["-f", "csv", "--top", "10"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(csv_rows(&output).len(), 4);
+    assert!(stderr(&output)
+        .contains("Note: --top 10 exceeds the 4 rated teams, so all of them are shown"));
+
+    let output = rasor_ratings(&league_server(), &["-f", "csv", "--top", "4"]);
+    assert_eq!(csv_rows(&output).len(), 4);
+    assert!(stderr(&output).is_empty());
+}
+
+#[test]
+fn baseline_matches_teams_by_id() {
+    let server = league_server();
+    let path = common::temp_dir("baseline").join("last-week.json");
+    // D was first and A second last week, under each other's names.
+    std::fs::write(
+        &path,
+        r#"[{"team_id": "D", "rank": 1, "team": "Team A"}, {"team_id": "A", "rank": 2, "team": "Team D"}]"#,
+    )
+    .unwrap();
+    let output = rasor_ratings(
+        &server,
+        &["-f", "csv", "--baseline", path.to_str().unwrap()],
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let movement: Vec<(String, String)> = csv_rows(&output)
+        .into_iter()
+        .map(|row| (row[2].clone(), row[1].clone()))
+        .collect();
+    assert_eq!(
+        movement,
+        [
+            ("Team A".to_string(), "+1".to_string()),
+            ("Team B".to_string(), "NEW".to_string()),
+            ("Team C".to_string(), "NEW".to_string()),
+            ("Team D".to_string(), "-3".to_string()),
+        ]
+    );
+
+    // A run's own JSON output works as the next run's baseline.
+    let json = rasor_ratings(&server, &["--json"]);
+    std::fs::write(&path, stdout(&json)).unwrap();
+    let output = rasor_ratings(
+        &server,
+        &["-f", "csv", "--baseline", path.to_str().unwrap()],
+    );
+    let movement: Vec<String> = csv_rows(&output)
+        .into_iter()
+        .map(|row| row[1].clone())
+        .collect();
+    assert_eq!(movement, ["=", "=", "=", "="]);
+}
+
+#[test]
+fn show_id_adds_the_espn_id() {
+    let team_ids = ["25", "usc", "TX-A_2"];
+    let games = vec![
+        common::game(0, ("25", 30), ("usc", 10)),
+        common::game(7, ("25", 20), ("TX-A_2", 10)),
+        common::game(14, ("usc", 20), ("TX-A_2", 10)),
+    ];
+    let server = MockServer::start(move |path, _| common::espn(path, &team_ids, &games));
+
+    let output = rasor_ratings(&server, &["-f", "csv", "--show-id"]);
+    assert!(stdout(&output).starts_with("#,Team,Team ID,"));
+    let ids: Vec<(String, String)> = csv_rows(&output)
+        .into_iter()
+        .map(|row| (row[1].clone(), row[2].clone()))
+        .collect();
+    assert_eq!(
+        ids,
+        [
+            ("Team 25".to_string(), "25".to_string()),
+            ("Team usc".to_string(), "usc".to_string()),
+            ("Team TX-A_2".to_string(), "TX-A_2".to_string()),
+        ]
+    );
+
+    let table = stdout(&rasor_ratings(&server, &["--show-id"]));
+    let fields = |line: &str| -> Vec<String> {
+        line.split('|')
+            .map(|field| field.trim().to_string())
+            .collect()
+    };
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(fields(lines[0])[2], "Team ID");
+    assert_eq!(fields(lines[2])[2], "25");
+    assert!(!stdout(&rasor_ratings(&server, &[])).contains("Team ID"));
+
+    // JSON always carries the ID, with or without --show-id.
+    for args in [&["--json", "--show-id"][..], &["--json"]] {
+        let output = rasor_ratings(&server, args);
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let teams = json["teams"].as_array().unwrap();
+        assert_eq!(teams[1]["team"], "Team usc");
+        assert_eq!(teams[1]["team_id"], "usc");
+    }
+}
+
+#[test]
+fn csv_with_show_id_works_as_a_baseline() {
+    let server = league_server();
+    let path = common::temp_dir("csv-baseline").join("last-week.csv");
+    std::fs::write(
+        &path,
+        stdout(&rasor_ratings(&server, &["-f", "csv", "--show-id"])),
+    )
+    .unwrap();
+    let output = rasor_ratings(
+        &server,
+        &["-f", "csv", "--baseline", path.to_str().unwrap()],
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let movement: Vec<String> = csv_rows(&output)
+        .into_iter()
+        .map(|row| row[1].clone())
+        .collect();
+    assert_eq!(movement, ["=", "=", "=", "="]);
+}
+
+#[test]
+fn groups_are_combined_without_duplicates() {
+    let group_path = |group: u16| {
+        format!(
+            "/sports/f/leagues/l/seasons/2024/types/2/groups/{}/teams?limit=1000&page=1",
+            group
+        )
+    };
+    let server = MockServer::start(move |path, _| {
+        if path == group_path(1) {
+            Reply::json(common::teams_page(&["A", "B"], 1, 1))
+        } else if path == group_path(4) {
+            Reply::json(common::teams_page(&["B", "C"], 1, 1))
+        } else {
+            common::espn(path, &["A", "B", "C", "D"], &common::league())
+        }
+    });
+
+    let output = rasor_ratings(&server, &["-f", "csv", "--group", "1", "--group", "4"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let mut teams = teams(&output);
+    teams.sort();
+    assert_eq!(teams, ["Team A", "Team B", "Team C"]);
+    assert_eq!(server.hits(&common::schedule_path("B")), 1);
+    assert_eq!(server.hits(&common::schedule_path("D")), 0);
+    assert_eq!(server.hits(&common::teams_page_path(1)), 0);
+}
+
+#[test]
+fn sport_league_and_season_fall_back_to_the_environment() {
+    let server = league_server();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rasor_ratings"))
+            .args(["--no-cache", "-q", "--max-retries", "0", "-f", "csv"])
+            .args([
+                "--site-api-base",
+                &server.url,
+                "--core-api-base",
+                &server.url,
+            ])
+            .args(args)
+            .env("RASOR_SPORT", "f")
+            .env("RASOR_LEAGUE", "l")
+            .env("RASOR_SEASON", "2024")
+            .env("RASOR_MAX_CONCURRENCY", "1")
+            .env_remove("RUST_LOG")
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&[]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(teams(&output), ["Team A", "Team B", "Team C", "Team D"]);
+
+    // Flags on the command line win over the environment.
+    let output = run(&["-S", "2023"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        server.hits("/sports/f/leagues/l/seasons/2023/teams?limit=1000&page=1"),
+        1
+    );
+}
+
+#[test]
+fn alphabetical_keeps_rating_ranks() {
+    // C beats both, and A beats B.
+    let games = vec![
+        common::game(0, ("C", 30), ("A", 10)),
+        common::game(7, ("C", 20), ("B", 10)),
+        common::game(14, ("A", 20), ("B", 10)),
+    ];
+    let server = MockServer::start(move |path, _| common::espn(path, &["A", "B", "C"], &games));
+
+    let output = rasor_ratings(&server, &["-f", "csv", "--alphabetical"]);
+    let ranks: Vec<(String, String)> = csv_rows(&output)
+        .into_iter()
+        .map(|row| (row[0].clone(), row[1].clone()))
+        .collect();
+    assert_eq!(
+        ranks,
+        [
+            ("2".to_string(), "Team A".to_string()),
+            ("3".to_string(), "Team B".to_string()),
+            ("1".to_string(), "Team C".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn quality_wins_count_wins_over_top_rated_opponents() {
+    // A and B rout P and Q, and X edges both of them.
+    let games = vec![
+        common::game(0, ("A", 42), ("P", 7)),
+        common::game(1, ("B", 38), ("Q", 3)),
+        common::game(7, ("A", 35), ("Q", 10)),
+        common::game(8, ("B", 31), ("P", 0)),
+        common::game(14, ("X", 21), ("A", 20)),
+        common::game(21, ("X", 17), ("B", 14)),
+        common::game(28, ("P", 24), ("X", 27)),
+    ];
+    let server =
+        MockServer::start(move |path, _| common::espn(path, &["A", "B", "P", "Q", "X"], &games));
+
+    let output = rasor_ratings(&server, &["-f", "csv", "--show-quality-wins"]);
+    let stdout = stdout(&output);
+    let headers: Vec<&str> = stdout.lines().next().unwrap().split(',').collect();
+    let qw = headers.iter().position(|header| *header == "QW").unwrap();
+    let quality_wins: Vec<(String, String)> = csv_rows(&output)
+        .into_iter()
+        .map(|row| (row[1].clone(), row[qw].clone()))
+        .collect();
+    assert!(
+        quality_wins.contains(&("Team X".to_string(), "2".to_string())),
+        "{}",
+        stdout
+    );
+    assert!(quality_wins.contains(&("Team Q".to_string(), "0".to_string())));
+}
+
+#[test]
+fn tie_ranks_share_a_rank_for_equal_displayed_ratings() {
+    // A and B play to a draw and each beat C by the same score.
+    let games = vec![
+        common::game(0, ("A", 10), ("B", 10)),
+        common::game(7, ("A", 20), ("C", 10)),
+        common::game(14, ("B", 20), ("C", 10)),
+    ];
+    let server = MockServer::start(move |path, _| common::espn(path, &["A", "B", "C"], &games));
+    let ranks = |output: &Output| -> Vec<String> {
+        csv_rows(output)
+            .into_iter()
+            .map(|row| row[0].clone())
+            .collect()
+    };
+
+    assert_eq!(
+        ranks(&rasor_ratings(&server, &["-f", "csv", "--tie-ranks"])),
+        ["1", "1", "3"]
+    );
+    assert_eq!(
+        ranks(&rasor_ratings(&server, &["-f", "csv"])),
+        ["1", "2", "3"]
+    );
+
+    // C (-7.00) and D (-10.00) differ, but both normalize to a z-score of -1 at precision 0.
+    let args = ["-f", "csv", "--precision", "0", "--normalize", "z-score"];
+    assert_eq!(
+        ranks(&rasor_ratings(&league_server(), &args)),
+        ["1", "2", "3", "4"]
+    );
+    let output = rasor_ratings(&league_server(), &[&args[..], &["--tie-ranks"]].concat());
+    assert_eq!(ranks(&output), ["1", "2", "3", "3"], "{}", stdout(&output));
+}
+
+#[test]
+fn markdown_has_a_separator_row_and_a_footer() {
+    let output = rasor_ratings(&league_server(), &["-f", "markdown"]);
+    let markdown = stdout(&output);
+    let lines: Vec<&str> = markdown.lines().collect();
+    assert!(lines[0].starts_with("| #"), "{}", markdown);
+    assert!(lines[1].starts_with("|---"), "{}", markdown);
+    assert!(
+        lines
+            .last()
+            .unwrap()
+            .starts_with("_Ratings for f/l, 2024 season: regular season games only;"),
+        "{}",
+        markdown
+    );
+
+    let table = stdout(&rasor_ratings(&league_server(), &[]));
+    assert!(!table.contains("_Ratings for"), "{}", table);
+}
+
+#[test]
+fn adjust_depth_one_matches_the_default() {
+    let default = rasor_ratings(&league_server(), &["-f", "csv"]);
+    let depth_one = rasor_ratings(&league_server(), &["-f", "csv", "--adjust-depth", "1"]);
+    assert_eq!(depth_one.status.code(), Some(0), "{}", stderr(&depth_one));
+    assert_eq!(stdout(&depth_one), stdout(&default));
+
+    let depth_two = rasor_ratings(&league_server(), &["-f", "csv", "--adjust-depth", "2"]);
+    assert_eq!(depth_two.status.code(), Some(0));
+    assert_ne!(stdout(&depth_two), stdout(&default));
+}
+
+#[test]
+fn output_redirected_to_a_file_is_plain_text() {
+    let server = league_server();
+    let path = common::temp_dir("redirected").join("ratings.txt");
+    // Progress bars are left on so that any drawn onto stdout would show up in the file.
+    let status = Command::new(env!("CARGO_BIN_EXE_rasor_ratings"))
+        .env_remove("RUST_LOG")
+        .args(["-s", "f", "-l", "l", "-S", "2024", "--no-cache"])
+        .args([
+            "--site-api-base",
+            &server.url,
+            "--core-api-base",
+            &server.url,
+        ])
+        .stdout(std::fs::File::create(&path).unwrap())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+
+    let output = std::fs::read_to_string(&path).unwrap();
+    assert!(output.contains("Team A"), "{}", output);
+    assert!(
+        !output
+            .chars()
+            .any(|c| c.is_control() && c != '\n' && c != '\t'),
+        "{:?}",
+        output
+    );
+}
+
+#[test]
+fn explain_reconstructs_the_overall_rating() {
+    let output = rasor_ratings(&league_server(), &["--explain", "--precision", "6"]);
+    let stdout = stdout(&output);
+    let fields = |line: &str| -> Vec<String> {
+        line.split('|')
+            .map(|field| field.trim().to_string())
+            .collect()
+    };
+    let mut lines = stdout
+        .lines()
+        .skip_while(|line| !line.starts_with("DEF = DEF sum / Weight, OFF = OFF sum / Weight"));
+    lines.next().expect("no explanation");
+    let headers = fields(lines.next().unwrap());
+    assert_eq!(
+        headers,
+        ["#", "Team", "Games", "Weight", "DEF sum", "OFF sum", "DEF", "OFF", "OVR"]
+    );
+    let rows: Vec<Vec<String>> = lines.skip(1).map(fields).collect();
+    assert_eq!(rows.len(), 4, "{}", stdout);
+    for row in rows {
+        let value = |col: usize| row[col].parse::<f64>().unwrap();
+        let (weight, defense_total, offense_total, overall) =
+            (value(3), value(4), value(5), value(8));
+        assert_eq!(row[2], "3", "{}", stdout);
+        assert!(
+            (defense_total / weight + offense_total / weight - overall).abs() < 1e-5,
+            "{}",
+            stdout
+        );
+    }
+}
@@ -0,0 +1,251 @@
+// SPDX-FileCopyrightText: 2024 Sebastian Rasor <https://www.sebastianrasor.com/contact>
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Fixtures shared by the integration tests. Not every test file uses all of them.
+#![allow(dead_code)]
+
+use chrono::{Days, NaiveDate};
+use rasor_ratings::{
+    ForfeitPolicy, Metric, Model, MovTransform, NameField, RatingOptions, SeasonTypeFilter,
+    TiePolicy,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A regular season game `day` days into September 2024, with the first team at home.
+pub fn game(day: u64, home: (&str, u32), away: (&str, u32)) -> Value {
+    let date = NaiveDate::from_ymd_opt(2024, 9, 1).unwrap() + Days::new(day);
+    json!({
+        "date": date.format("%Y-%m-%dT17:00Z").to_string(),
+        "seasonType": {"id": "2"},
+        "week": {"number": day / 7 + 1},
+        "competitions": [{
+            "competitors": [
+                {"id": home.0, "homeAway": "home", "score": {"value": home.1}},
+                {"id": away.0, "homeAway": "away", "score": {"value": away.1}},
+            ],
+        }],
+    })
+}
+
+/// A team's schedule response as ESPN would send it: every game the team played.
+pub fn schedule(team_id: &str, games: &[Value]) -> Value {
+    let events: Vec<&Value> = games
+        .iter()
+        .filter(|game| {
+            game["competitions"][0]["competitors"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|competitor| competitor["id"] == team_id)
+        })
+        .collect();
+    json!({
+        "team": {"id": team_id, "location": format!("Team {}", team_id)},
+        "events": events,
+    })
+}
+
+/// Three teams where A beat B 30-10 and C 20-10, and B beat C 20-10. By default A rates 5 DEF
+/// and 10 OFF, B -5 and 0, and C 0 and -10.
+pub fn round_robin() -> Vec<Value> {
+    vec![
+        game(0, ("A", 30), ("B", 10)),
+        game(7, ("A", 20), ("C", 10)),
+        game(14, ("B", 20), ("C", 10)),
+    ]
+}
+
+/// Four teams that each played the others once. A won all three of its games.
+pub fn league() -> Vec<Value> {
+    vec![
+        game(0, ("A", 30), ("B", 10)),
+        game(1, ("C", 13), ("D", 14)),
+        game(7, ("A", 20), ("C", 10)),
+        game(8, ("B", 24), ("D", 21)),
+        game(14, ("D", 7), ("A", 35)),
+        game(15, ("B", 20), ("C", 10)),
+    ]
+}
+
+/// The command line's defaults.
+pub fn options() -> RatingOptions {
+    RatingOptions {
+        model: Model::OpponentAverage,
+        iterations: 1,
+        adjustment_decay: 1.0,
+        mov_cap: None,
+        mov_transform: MovTransform::None,
+        home_advantage: 0.0,
+        neutralize_opponents: false,
+        recency_halflife: None,
+        season_type: SeasonTypeFilter::Regular,
+        week: None,
+        include_all_opponents: false,
+        name_field: NameField::Location,
+        pyth_exponent: 2.37,
+        elo: false,
+        elo_k_factor: 20.0,
+        elo_mov_weight: 1.0,
+        exclude_overtime: false,
+        neutral_only: false,
+        ties: TiePolicy::Count,
+        last_n_games: None,
+        min_opponent_games: 1,
+        metric: Metric::Score,
+        forfeit_policy: ForfeitPolicy::Skip,
+    }
+}
+
+/// What the mock server sends back for one request.
+pub struct Reply {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: String,
+}
+
+impl Reply {
+    pub fn json(body: Value) -> Reply {
+        Reply::raw(200, &body.to_string())
+    }
+
+    pub fn status(status: u16) -> Reply {
+        Reply::raw(status, "")
+    }
+
+    pub fn raw(status: u16, body: &str) -> Reply {
+        Reply {
+            status,
+            headers: vec![],
+            body: body.to_string(),
+        }
+    }
+
+    pub fn header(mut self, name: &'static str, value: &str) -> Reply {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+}
+
+/// A plain HTTP server on a local port that answers every GET through `handler`, which is given
+/// the path with its query and how many times that path was requested before. It runs until the
+/// test process exits.
+pub struct MockServer {
+    pub url: String,
+    hits: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl MockServer {
+    pub fn start(handler: impl Fn(&str, usize) -> Reply + Send + Sync + 'static) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let hits: Arc<Mutex<HashMap<String, usize>>> = Arc::default();
+        let handler = Arc::new(handler);
+        let server_hits = Arc::clone(&hits);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let hits = Arc::clone(&server_hits);
+                let handler = Arc::clone(&handler);
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(&stream);
+                    let mut request_line = String::new();
+                    if reader.read_line(&mut request_line).is_err() {
+                        return;
+                    }
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                        line.clear();
+                    }
+                    let path = request_line
+                        .split_whitespace()
+                        .nth(1)
+                        .unwrap_or_default()
+                        .to_string();
+                    let count = {
+                        let mut hits = hits.lock().unwrap();
+                        let count = hits.entry(path.clone()).or_default();
+                        *count += 1;
+                        *count - 1
+                    };
+                    let reply = handler(&path, count);
+                    let mut response = format!(
+                        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+                        reply.status,
+                        reply.body.len()
+                    );
+                    for (name, value) in &reply.headers {
+                        response.push_str(&format!("{}: {}\r\n", name, value));
+                    }
+                    response.push_str("\r\n");
+                    response.push_str(&reply.body);
+                    let _ = stream.write_all(response.as_bytes());
+                });
+            }
+        });
+        MockServer { url, hits }
+    }
+
+    /// How many requests were made for `path`, query included.
+    pub fn hits(&self, path: &str) -> usize {
+        self.hits.lock().unwrap().get(path).copied().unwrap_or(0)
+    }
+
+    /// How many requests were made in all.
+    pub fn total_hits(&self) -> usize {
+        self.hits.lock().unwrap().values().sum()
+    }
+}
+
+/// A fresh, empty directory under the system's temporary directory.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rasor_ratings-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub const TEAMS_PATH: &str = "/sports/f/leagues/l/seasons/2024/teams";
+
+/// One page of the teams listing, referring to each team the way ESPN does.
+pub fn teams_page(team_ids: &[&str], page_index: u64, page_count: u64) -> Value {
+    let items: Vec<Value> = team_ids
+        .iter()
+        .map(|team_id| {
+            json!({"$ref": format!("http://core/sports/f/leagues/l/seasons/2024/teams/{}?lang=en", team_id)})
+        })
+        .collect();
+    json!({"pageIndex": page_index, "pageCount": page_count, "items": items})
+}
+
+/// The path of a page of the teams listing.
+pub fn teams_page_path(page_index: u64) -> String {
+    format!("{}?limit=1000&page={}", TEAMS_PATH, page_index)
+}
+
+/// The path of a team's 2024 schedule.
+pub fn schedule_path(team_id: &str) -> String {
+    format!("/sports/f/l/teams/{}/schedule?season=2024", team_id)
+}
+
+/// Answers like ESPN would for sport `f`, league `l`, and the 2024 season: one page listing
+/// `team_ids`, and each listed team's schedule out of `games`. Anything else is a 404.
+pub fn espn(path: &str, team_ids: &[&str], games: &[Value]) -> Reply {
+    if path.starts_with(&format!("{}?", TEAMS_PATH)) {
+        return Reply::json(teams_page(team_ids, 1, 1));
+    }
+    match team_ids
+        .iter()
+        .find(|team_id| path == schedule_path(team_id))
+    {
+        Some(team_id) => Reply::json(schedule(team_id, games)),
+        None => Reply::status(404),
+    }
+}
@@ -0,0 +1,224 @@
+// SPDX-FileCopyrightText: 2024 Sebastian Rasor <https://www.sebastianrasor.com/contact>
+// SPDX-License-Identifier: AGPL-3.0-only
+
+mod common;
+
+use common::{MockServer, Reply};
+use rasor_ratings::{
+    Cache, Endpoints, EspnProvider, Fetcher, PaginatedItems, ScheduleProvider, TeamSchedule,
+};
+use reqwest::Client;
+use serde_json::json;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn fetcher(cache: Option<Cache>, max_retries: u32) -> Fetcher {
+    Fetcher {
+        client: Client::new(),
+        cache,
+        max_retries,
+    }
+}
+
+/// An empty cache in a fresh directory.
+fn temp_cache(name: &str) -> Cache {
+    Cache {
+        dir: common::temp_dir(name),
+        ttl: Duration::from_secs(3600),
+    }
+}
+
+/// ESPN's sport `f` and league `l`, as served by `server`.
+fn provider(server: &MockServer, fetcher: Fetcher) -> EspnProvider {
+    EspnProvider {
+        fetcher,
+        endpoints: Endpoints::new(&server.url, &server.url, "f", "l", 2),
+        teams_cache: None,
+        max_concurrency: 4,
+        quiet: true,
+    }
+}
+
+/// Fetches the 2024 team IDs from `server`.
+async fn team_ids(server: &MockServer) -> anyhow::Result<Vec<String>> {
+    provider(server, fetcher(None, 0))
+        .team_ids(2024, None)
+        .await
+}
+
+#[tokio::test]
+async fn a_populated_cache_makes_no_requests() {
+    let server = MockServer::start(|_, _| Reply::json(common::teams_page(&[], 1, 1)));
+    let url = format!("{}/teams", server.url);
+    let fetcher = fetcher(Some(temp_cache("cache")), 0);
+
+    for _ in 0..2 {
+        let page: PaginatedItems = fetcher.fetch_json(&url).await.unwrap();
+        assert_eq!(page.page_count, 1.into());
+    }
+    assert_eq!(server.total_hits(), 1);
+}
+
+#[tokio::test]
+async fn refetches_a_corrupt_cache_entry() {
+    let server = MockServer::start(|_, _| Reply::json(common::teams_page(&[], 1, 1)));
+    let url = format!("{}/teams", server.url);
+    let cache = temp_cache("corrupt-cache");
+    cache.put(&url, "{\"pageIndex\": 1, \"pageCo").unwrap();
+
+    let page: PaginatedItems = fetcher(Some(cache.clone()), 0)
+        .fetch_json(&url)
+        .await
+        .unwrap();
+    assert_eq!(page.page_count, 1.into());
+    assert_eq!(server.total_hits(), 1);
+    assert_eq!(
+        cache.get(&url).unwrap(),
+        common::teams_page(&[], 1, 1).to_string()
+    );
+}
+
+#[tokio::test]
+async fn retries_a_server_error() {
+    let server = MockServer::start(|_, count| match count {
+        0 | 1 => Reply::status(503),
+        _ => Reply::json(common::schedule("A", &[])),
+    });
+    let url = format!("{}/teams/A/schedule", server.url);
+    let schedule: TeamSchedule = fetcher(None, 3).fetch_json(&url).await.unwrap();
+    assert_eq!(schedule.team.id, "A");
+    assert_eq!(server.total_hits(), 3);
+}
+
+#[tokio::test]
+async fn waits_out_a_rate_limit() {
+    let server = MockServer::start(|path, count| match count {
+        0 => Reply::status(429).header("Retry-After", "1"),
+        _ => common::espn(path, &["A"], &common::round_robin()),
+    });
+    let provider = provider(&server, fetcher(None, 1));
+    let started = Instant::now();
+    let schedule = provider.schedule("A", 2024).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert_eq!(schedule.team.id, "A");
+    assert_eq!(server.hits(&common::schedule_path("A")), 2);
+}
+
+#[tokio::test]
+async fn a_schedule_that_does_not_parse_is_an_error() {
+    let server = MockServer::start(|path, _| match path == common::schedule_path("B") {
+        true => Reply::raw(200, "{\"team\": "),
+        false => common::espn(path, &["A", "B"], &common::round_robin()),
+    });
+    let provider = provider(&server, fetcher(None, 0));
+
+    assert_eq!(provider.schedule("A", 2024).await.unwrap().team.id, "A");
+    let error = provider.schedule("B", 2024).await.unwrap_err();
+    assert!(error.to_string().contains("EOF while parsing"), "{}", error);
+}
+
+#[tokio::test]
+async fn fetches_every_page_of_teams_in_order() {
+    let server = MockServer::start(|path, _| {
+        let Some(page) = path.strip_prefix(&format!("{}?limit=1000&page=", common::TEAMS_PATH))
+        else {
+            return Reply::status(404);
+        };
+        let page: u64 = page.parse().unwrap();
+        // The second page answers last, but its teams still come before the third's.
+        if page == 2 {
+            thread::sleep(Duration::from_millis(100));
+        }
+        let team_ids: Vec<String> = (0..2).map(|i| (2 * page + i).to_string()).collect();
+        let team_ids: Vec<&str> = team_ids.iter().map(String::as_str).collect();
+        Reply::json(common::teams_page(&team_ids, page, 3))
+    });
+
+    assert_eq!(
+        team_ids(&server).await.unwrap(),
+        ["2", "3", "4", "5", "6", "7"]
+    );
+    assert_eq!(server.total_hits(), 3);
+}
+
+#[tokio::test]
+async fn lists_a_team_repeated_across_pages_once() {
+    let server = MockServer::start(|path, _| {
+        let pages = [["A", "B"], ["B", "C"], ["A", "D"]];
+        match (1..=3).find(|&page| path == common::teams_page_path(page)) {
+            Some(page) => Reply::json(common::teams_page(&pages[page as usize - 1], page, 3)),
+            None => Reply::status(404),
+        }
+    });
+    assert_eq!(team_ids(&server).await.unwrap(), ["A", "B", "C", "D"]);
+}
+
+#[tokio::test]
+async fn cached_team_ids_skip_pagination_but_not_schedules() {
+    let server =
+        MockServer::start(|path, _| common::espn(path, &["A", "B", "C"], &common::round_robin()));
+    let teams_cache = temp_cache("teams-cache");
+
+    for _ in 0..2 {
+        let provider = EspnProvider {
+            teams_cache: Some(teams_cache.clone()),
+            ..provider(&server, fetcher(None, 0))
+        };
+        let team_ids = provider.team_ids(2024, None).await.unwrap();
+        assert_eq!(team_ids, ["A", "B", "C"]);
+        provider.schedule("A", 2024).await.unwrap();
+    }
+    assert_eq!(server.hits(&common::teams_page_path(1)), 1);
+    assert_eq!(server.hits(&common::schedule_path("A")), 2);
+}
+
+#[tokio::test]
+async fn rejects_a_stuck_page_index() {
+    // Every page claims to be the first of three.
+    let server = MockServer::start(|_, _| Reply::json(common::teams_page(&["A"], 1, 3)));
+    let error = team_ids(&server).await.unwrap_err();
+    assert_eq!(
+        format!("{:#}", error),
+        "failed to fetch the teams listing: inconsistent pagination: requested pages 1..=3 but got page indices [1, 1, 1] with page counts [3, 3, 3]"
+    );
+    assert_eq!(server.total_hits(), 3);
+}
+
+#[tokio::test]
+async fn refuses_an_implausible_page_count() {
+    let server = MockServer::start(|_, _| {
+        Reply::json(json!({"pageIndex": 1, "pageCount": 1_000_000, "items": []}))
+    });
+    let error = team_ids(&server).await.unwrap_err();
+    assert!(
+        format!("{:#}", error).contains("reported 1000000 pages"),
+        "{:#}",
+        error
+    );
+    assert_eq!(server.total_hits(), 1);
+}
+
+#[tokio::test]
+async fn group_teams_use_the_season_type_code() {
+    let endpoints = Endpoints::new("http://site", "http://core", "f", "l", 3);
+    assert_eq!(
+        endpoints.teams(&2024, Some(&5)),
+        "http://core/sports/f/leagues/l/seasons/2024/types/3/groups/5/teams"
+    );
+    assert_eq!(
+        endpoints.group(&2024, "5"),
+        "http://core/sports/f/leagues/l/seasons/2024/types/3/groups/5"
+    );
+
+    let group_path = "/sports/f/leagues/l/seasons/2024/types/3/groups/5/teams?limit=1000&page=1";
+    let server = MockServer::start(move |path, _| match path == group_path {
+        true => Reply::json(common::teams_page(&["A", "B"], 1, 1)),
+        false => Reply::status(404),
+    });
+    let provider = EspnProvider {
+        endpoints: Endpoints::new(&server.url, &server.url, "f", "l", 3),
+        ..provider(&server, fetcher(None, 0))
+    };
+    assert_eq!(provider.team_ids(2024, Some(5)).await.unwrap(), ["A", "B"]);
+    assert_eq!(server.hits(group_path), 1);
+}
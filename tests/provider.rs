@@ -0,0 +1,110 @@
+// SPDX-FileCopyrightText: 2024 Sebastian Rasor <https://www.sebastianrasor.com/contact>
+// SPDX-License-Identifier: AGPL-3.0-only
+
+mod common;
+
+use anyhow::{anyhow, Result};
+use futures::future::{try_join_all, BoxFuture};
+use futures::FutureExt;
+use rasor_ratings::{compute_ratings, ScheduleProvider, TeamSchedule};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Serves a fixed league from memory, with each group listing some of its teams.
+struct MockProvider {
+    team_ids: Vec<&'static str>,
+    groups: HashMap<u16, Vec<&'static str>>,
+    games: Vec<Value>,
+}
+
+impl ScheduleProvider for MockProvider {
+    fn team_ids(&self, _season: u16, group: Option<u16>) -> BoxFuture<'_, Result<Vec<String>>> {
+        async move {
+            let team_ids = match group {
+                Some(group) => self
+                    .groups
+                    .get(&group)
+                    .ok_or_else(|| anyhow!("no group {}", group))?,
+                None => &self.team_ids,
+            };
+            Ok(team_ids.iter().map(|team_id| team_id.to_string()).collect())
+        }
+        .boxed()
+    }
+
+    fn schedule<'a>(
+        &'a self,
+        team_id: &'a str,
+        _season: u16,
+    ) -> BoxFuture<'a, Result<TeamSchedule>> {
+        async move {
+            if !self.team_ids.contains(&team_id) {
+                return Err(anyhow!("no team {}", team_id));
+            }
+            Ok(serde_json::from_value(common::schedule(
+                team_id,
+                &self.games,
+            ))?)
+        }
+        .boxed()
+    }
+
+    fn schedule_source(&self, team_id: &str, season: u16) -> String {
+        format!("mock://{}/{}", season, team_id)
+    }
+}
+
+fn provider() -> Arc<dyn ScheduleProvider> {
+    Arc::new(MockProvider {
+        team_ids: vec!["A", "B", "C"],
+        groups: HashMap::from([(1, vec!["A", "B"])]),
+        games: common::round_robin(),
+    })
+}
+
+async fn rate(provider: &dyn ScheduleProvider, group: Option<u16>) -> Result<Vec<(String, f64)>> {
+    let team_ids = provider.team_ids(2024, group).await?;
+    let team_schedules = try_join_all(
+        team_ids
+            .iter()
+            .map(|team_id| provider.schedule(team_id, 2024)),
+    )
+    .await?;
+    let league_team_ids: Vec<&str> = team_ids.iter().map(String::as_str).collect();
+    let mut team_ratings: Vec<(String, f64)> =
+        compute_ratings(&team_schedules, &league_team_ids, &common::options())
+            .into_iter()
+            .map(|rating| (rating.id.clone(), rating.overall_rating()))
+            .collect();
+    team_ratings.sort_by(|(id1, _), (id2, _)| id1.cmp(id2));
+    Ok(team_ratings)
+}
+
+#[tokio::test]
+async fn rates_a_league_from_a_provider() {
+    let team_ratings = rate(provider().as_ref(), None).await.unwrap();
+    assert_eq!(
+        team_ratings,
+        vec![
+            ("A".to_string(), 15.0),
+            ("B".to_string(), -5.0),
+            ("C".to_string(), -10.0),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn rates_only_a_groups_teams() {
+    // Without C, A and B's only game has no other games to compare against.
+    let team_ratings = rate(provider().as_ref(), Some(1)).await.unwrap();
+    assert!(team_ratings.is_empty());
+}
+
+#[tokio::test]
+async fn reports_schedules_the_provider_cant_load() {
+    let provider = provider();
+    let error = provider.schedule("Z", 2024).await.unwrap_err();
+    assert_eq!(error.to_string(), "no team Z");
+    assert_eq!(provider.schedule_source("Z", 2024), "mock://2024/Z");
+}